@@ -0,0 +1,178 @@
+use std::{
+    borrow::Cow,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{
+    config::Settings,
+    file::{self, LineEnding},
+    history::{Cursor, History},
+    piece_table::PieceTable,
+    syntax::{Highlighter, Syntax},
+};
+
+// one open file, with everything that goes with it including where the cursor was left
+pub struct Buffer {
+    pub text: PieceTable,
+    pub history: History,
+    pub highlighter: Highlighter,
+    pub filename: Option<PathBuf>,
+    pub dirty: bool,
+    pub invalid_utf8: bool, // the file had bytes that arent utf-8, kept as is and shown as \xNN
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub settings: Settings, // tabs and wrapping, which can depend on the filetype
+    pub cx: u32,
+    pub cy: u32,
+    pub rx: u32,
+    pub row_offset: usize,
+    pub wrap_offset: usize, // rows of the top line scrolled off the window when wrapping
+    pub col_offset: usize,
+    pub anchor: Option<Cursor>, // the other end of the selection from the cursor
+    pub mark_set: bool, // set with the set-mark key, so plain movement extends the selection
+}
+
+// where the cursor and the scroll are, each window showing a buffer has its own
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct View {
+    pub cx: u32,
+    pub cy: u32,
+    pub rx: u32,
+    pub row_offset: usize,
+    pub wrap_offset: usize,
+    pub col_offset: usize,
+    pub anchor: Option<Cursor>,
+    pub mark_set: bool,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Buffer {
+            text: PieceTable::new(),
+            history: History::new(),
+            highlighter: Highlighter::new(None),
+            filename: None,
+            dirty: false,
+            invalid_utf8: false,
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            settings: Settings::default(),
+            cx: 0,
+            cy: 0,
+            rx: 0,
+            row_offset: 0,
+            wrap_offset: 0,
+            col_offset: 0,
+            anchor: None,
+            mark_set: false,
+        }
+    }
+
+    pub fn open(filename: PathBuf) -> io::Result<Self> {
+        let contents = fs::read(&filename)?;
+        let mut buffer = Buffer::new();
+        buffer.highlighter = Highlighter::new(Syntax::for_filename(&filename));
+        buffer.filename = Some(filename);
+
+        // work on the raw bytes so nothing gets lost if the file isnt valid utf-8
+        let contents = file::decode(&contents);
+        buffer.invalid_utf8 = contents.invalid_utf8;
+        buffer.line_ending = contents.line_ending;
+        buffer.final_newline = contents.final_newline;
+        buffer.text = PieceTable::from_bytes(contents.text);
+
+        Ok(buffer)
+    }
+
+    pub fn view(&self) -> View {
+        View {
+            cx: self.cx,
+            cy: self.cy,
+            rx: self.rx,
+            row_offset: self.row_offset,
+            wrap_offset: self.wrap_offset,
+            col_offset: self.col_offset,
+            anchor: self.anchor,
+            mark_set: self.mark_set,
+        }
+    }
+
+    // pulled back inside the text, which another window might have changed since the view was
+    // taken
+    pub fn set_view(&mut self, view: View) {
+        let lines = self.text.line_count();
+        let clamp = |(cx, cy): Cursor| {
+            let cy = cy.min(lines as u32);
+            let len = if (cy as usize) < lines {
+                self.text.line_len(cy as usize)
+            } else {
+                0
+            };
+            (cx.min(len as u32), cy)
+        };
+        (self.cx, self.cy) = clamp((view.cx, view.cy));
+        self.rx = view.rx;
+        self.row_offset = view.row_offset.min(lines);
+        self.wrap_offset = view.wrap_offset; // scroll pulls it back if the line got shorter
+        self.col_offset = view.col_offset;
+        self.anchor = view.anchor.map(clamp);
+        self.mark_set = view.mark_set;
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+        self.history.mark_saved();
+    }
+
+    // the empty one the editor starts with, which the first file opened takes the place of
+    pub fn is_scratch(&self) -> bool {
+        self.filename.is_none() && !self.dirty && self.text.is_empty()
+    }
+
+    pub fn name(&self) -> Cow<'_, str> {
+        match &self.filename {
+            Some(filename) => filename.to_string_lossy(),
+            None => "[No Name]".into(),
+        }
+    }
+
+    pub fn set_filename(&mut self, filename: PathBuf) {
+        self.highlighter = Highlighter::new(Syntax::for_filename(Path::new(&filename)));
+        self.filename = Some(filename);
+    }
+
+    // the bytes as they go on disk
+    pub fn encode(&self) -> Vec<u8> {
+        file::encode(&self.text.bytes(), self.line_ending, self.final_newline)
+    }
+
+    // for when the editor is going down hard. the unsaved changes go in a file next to the
+    // original, or in the temp dir if that cant be written, and the path is printed so they can
+    // be found again. the terminal should already be restored
+    pub fn write_recovery(&self) {
+        if !self.dirty {
+            return;
+        }
+        let name = self
+            .filename
+            .as_ref()
+            .and_then(|filename| filename.file_name())
+            .map_or("unnamed".into(), |name| name.to_string_lossy());
+        let recovery = format!("{}.mega-recovery-{}", name, process::id());
+        let beside = match &self.filename {
+            Some(filename) => filename.with_file_name(&recovery),
+            None => PathBuf::from(&recovery),
+        };
+
+        let buf = self.encode();
+        for path in [beside, env::temp_dir().join(&recovery)] {
+            if file::write(&path, &buf).is_ok() {
+                eprintln!("mega: unsaved changes written to {}", path.display());
+                return;
+            }
+        }
+        eprintln!("mega: couldnt write the unsaved changes anywhere, they are lost");
+    }
+}
@@ -5,7 +5,81 @@ pub const HIDE_CURSOR: &[u8] = b"\x1b[?25l";
 pub const SHOW_CURSOR: &[u8] = b"\x1b[?25h";
 pub const INVERTED_COLORS: &[u8] = b"\x1b[7m";
 pub const NORMAL_COLORS: &[u8] = b"\x1b[m";
+pub const DEFAULT_FOREGROUND: &[u8] = b"\x1b[39m";
+pub const QUERY_DEVICE_ATTRIBUTES: &[u8] = b"\x1b[c";
+pub const QUERY_KITTY_KEYBOARD: &[u8] = b"\x1b[?u";
+pub const PUSH_KITTY_KEYBOARD: &[u8] = b"\x1b[>1u"; // 1 is just "disambiguate escape codes"
+pub const POP_KITTY_KEYBOARD: &[u8] = b"\x1b[<u";
+// button presses, releases and drags with the button held, reported in the SGR format
+pub const ENABLE_MOUSE: &[u8] = b"\x1b[?1002h\x1b[?1006h";
+pub const DISABLE_MOUSE: &[u8] = b"\x1b[?1006l\x1b[?1002l";
+pub const ENABLE_BRACKETED_PASTE: &[u8] = b"\x1b[?2004h";
+pub const DISABLE_BRACKETED_PASTE: &[u8] = b"\x1b[?2004l";
 
-pub fn move_cursor(row: u32, col: u32) -> &'static [u8] {
-    format!("\x1b[{row};{col}H").leak().as_bytes()
+pub fn move_cursor(row: u32, col: u32) -> Vec<u8> {
+    format!("\x1b[{row};{col}H").into_bytes()
+}
+
+pub fn set_foreground(color: u8) -> Vec<u8> {
+    format!("\x1b[{color}m").into_bytes()
+}
+
+// takes the foreground code for the color, the background ones are the same plus 10
+pub fn set_background(color: u8) -> Vec<u8> {
+    format!("\x1b[{}m", color + 10).into_bytes()
+}
+
+// OSC 52, asks the terminal to put `bytes` on the system clipboard. goes through ssh and tmux,
+// which a clipboard tool run on this machine wouldnt
+pub fn set_clipboard(bytes: &[u8]) -> Vec<u8> {
+    let mut command = b"\x1b]52;c;".to_vec();
+    command.extend_from_slice(&base64(bytes));
+    command.push(b'\x07');
+    command
+}
+
+fn base64(bytes: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]);
+            } else {
+                encoded.push(b'=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        // the examples from rfc 4648
+        for (bytes, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(bytes.as_bytes()), encoded.as_bytes());
+        }
+        assert_eq!(base64(b"\xff\xfe\x00"), b"//4A");
+    }
+
+    #[test]
+    fn clipboard() {
+        assert_eq!(set_clipboard("é\n".as_bytes()), b"\x1b]52;c;w6kK\x07");
+    }
 }
@@ -0,0 +1,614 @@
+use std::{env, fs, io, path::PathBuf, time::Duration};
+
+use crate::{
+    editor::{LineNumbers, Wrap},
+    keys::{Key, KeyCode},
+    syntax::{Highlight, Syntax},
+};
+
+// what can be changed from $XDG_CONFIG_HOME/mega/config. its lines of `name = value`, with
+// `[keys]`, `[colors]` and `[filetype.<name>]` sections after the plain settings:
+//
+//     tab_width = 4
+//     line_numbers = relative
+//     theme = bright
+//
+//     [keys]
+//     ctrl-g = find
+//     ctrl-f = none
+//
+//     [colors]
+//     comment = bright-black
+//
+//     [filetype.markdown]
+//     wrap = words
+//
+// a # after a space starts a comment. names and values can be quoted to have one in them, like
+// "#" = save
+pub struct Config {
+    pub settings: Settings,
+    pub filetypes: Vec<(&'static str, Settings)>, // by syntax name
+    pub line_numbers: LineNumbers,
+    pub theme: Theme,
+    pub keymap: Keymap,
+    pub quit_times: usize,
+    pub message_timeout: Duration,
+    pub errors: Vec<String>, // whatever couldnt be understood, with the line it was on
+}
+
+// the settings every buffer has its own copy of, so they can differ by filetype
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    pub tab_width: usize,
+    pub expand_tabs: bool, // the tab key puts in spaces
+    pub wrap: Wrap,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            tab_width: 8,
+            expand_tabs: false,
+            wrap: Wrap::Off,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            settings: Settings::default(),
+            filetypes: Vec::new(),
+            line_numbers: LineNumbers::Off,
+            theme: Theme::default(),
+            keymap: Keymap::default(),
+            quit_times: 3,
+            message_timeout: Duration::from_secs(5),
+            errors: Vec::new(),
+        }
+    }
+}
+
+enum Section {
+    Settings,
+    Keys,
+    Colors,
+    Filetype(usize), // where it is in filetypes
+}
+
+impl Config {
+    // the defaults if there is no config file, and the defaults plus an error if it cant be read
+    pub fn load() -> Config {
+        let Some(path) = path() else {
+            return Config::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Config::default(),
+            Err(error) => Config {
+                errors: vec![format!("cant read {}: {}", path.display(), error)],
+                ..Config::default()
+            },
+        }
+    }
+
+    pub fn parse(text: &str) -> Config {
+        let mut config = Config::default();
+        let mut section = Section::Settings;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(error) = config.line(line, &mut section) {
+                config.errors.push(format!("line {}: {}", n + 1, error));
+            }
+        }
+        config
+    }
+
+    fn line(&mut self, line: &str, section: &mut Section) -> Result<(), String> {
+        if let Some(header) = line.strip_prefix('[') {
+            let (header, rest) = token(header.trim_start(), Some(']'))?;
+            let rest = rest
+                .strip_prefix(']')
+                .ok_or("missing ] at the end of the section")?;
+            end_of_line(rest)?;
+            *section = self.section(header)?;
+            return Ok(());
+        }
+        let (name, rest) = token(line, Some('='))?;
+        let rest = match rest.trim_start().strip_prefix('=') {
+            Some(rest) if !name.is_empty() => rest,
+            _ => return Err("expected name = value".to_string()),
+        };
+        let (value, rest) = token(rest.trim_start(), None)?;
+        end_of_line(rest)?;
+        self.set(section, name, value)
+    }
+
+    fn section(&mut self, header: &str) -> Result<Section, String> {
+        match header {
+            "keys" => Ok(Section::Keys),
+            "colors" => Ok(Section::Colors),
+            _ => {
+                let name = header
+                    .strip_prefix("filetype.")
+                    .ok_or(format!("unknown section [{}]", header))?;
+                let syntax = Syntax::named(name).ok_or(format!("unknown filetype {}", name))?;
+                // starting from the plain settings, which come before any section
+                self.filetypes.push((syntax.name, self.settings));
+                Ok(Section::Filetype(self.filetypes.len() - 1))
+            }
+        }
+    }
+
+    fn set(&mut self, section: &Section, name: &str, value: &str) -> Result<(), String> {
+        match section {
+            Section::Settings => match name {
+                "line_numbers" => {
+                    self.line_numbers = match value {
+                        "off" | "false" => LineNumbers::Off,
+                        "absolute" | "on" | "true" => LineNumbers::Absolute,
+                        "relative" => LineNumbers::Relative,
+                        "hybrid" => LineNumbers::Hybrid,
+                        _ => return Err(expected("off, absolute, relative or hybrid", value)),
+                    }
+                }
+                "theme" => {
+                    self.theme = Theme::named(value)
+                        .ok_or_else(|| expected("default, bright or mono", value))?;
+                }
+                "quit_times" => self.quit_times = number(value, 1, 100)?,
+                "message_timeout" => {
+                    self.message_timeout = Duration::from_secs(number(value, 0, 3600)? as u64);
+                }
+                _ => set_setting(&mut self.settings, name, value)?,
+            },
+            Section::Filetype(index) => set_setting(&mut self.filetypes[*index].1, name, value)?,
+            Section::Keys => {
+                let key = Key::parse(name).ok_or(format!("unknown key {}", name))?;
+                let action = match value {
+                    "none" => None,
+                    _ => Some(Action::named(value).ok_or(format!("unknown action {}", value))?),
+                };
+                self.keymap.bind(key, action);
+            }
+            Section::Colors => {
+                let color = color(value)?;
+                let theme = &mut self.theme;
+                match name {
+                    "keyword" => theme.keyword = color,
+                    "type" => theme.types = color,
+                    "string" => theme.string = color,
+                    "number" => theme.number = color,
+                    "comment" => theme.comment = color,
+                    "gutter" => theme.gutter = color,
+                    "match" => theme.matches = color,
+                    _ => return Err(format!("unknown color {}", name)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // what a buffer for this filetype starts out with
+    pub fn settings_for(&self, syntax: Option<&Syntax>) -> Settings {
+        syntax
+            .and_then(|syntax| {
+                self.filetypes
+                    .iter()
+                    .rfind(|(name, _)| *name == syntax.name)
+            })
+            .map_or(self.settings, |&(_, settings)| settings)
+    }
+}
+
+// the ones that can go in a filetype section as well as at the top
+fn set_setting(settings: &mut Settings, name: &str, value: &str) -> Result<(), String> {
+    match name {
+        "tab_width" => settings.tab_width = number(value, 1, 32)?,
+        "expand_tabs" => settings.expand_tabs = boolean(value)?,
+        "wrap" => {
+            settings.wrap = match value {
+                "off" | "false" => Wrap::Off,
+                "on" | "true" => Wrap::Anywhere,
+                "words" => Wrap::Words,
+                _ => return Err(expected("off, on or words", value)),
+            }
+        }
+        _ => return Err(format!("unknown setting {}", name)),
+    }
+    Ok(())
+}
+
+// $XDG_CONFIG_HOME, or ~/.config where that isnt set like the spec says
+fn path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("mega").join("config"))
+}
+
+// a name or value, and the rest of the line after it. a quoted one can have anything but a quote
+// in it, a bare one stops at `end` or at a # after a space, which starts a comment
+fn token(text: &str, end: Option<char>) -> Result<(&str, &str), String> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let close = quoted.find('"').ok_or("missing closing quote")?;
+        return Ok((&quoted[..close], &quoted[close + 1..]));
+    }
+    let mut prev = ' ';
+    let stop = text
+        .char_indices()
+        .find(|&(_, c)| {
+            let found = Some(c) == end || (c == '#' && prev.is_whitespace());
+            prev = c;
+            found
+        })
+        .map_or(text.len(), |(i, _)| i);
+    Ok((text[..stop].trim_end(), &text[stop..]))
+}
+
+// nothing but a comment can come after the value or the section
+fn end_of_line(rest: &str) -> Result<(), String> {
+    let rest = rest.trim();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected {} at the end of the line", rest))
+    }
+}
+
+fn expected(what: &str, value: &str) -> String {
+    format!("expected {}, not {}", what, value)
+}
+
+fn number(value: &str, min: usize, max: usize) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n),
+        _ => Err(expected(
+            &format!("a number from {} to {}", min, max),
+            value,
+        )),
+    }
+}
+
+fn boolean(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(expected("true or false", value)),
+    }
+}
+
+const COLORS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+// as the ansi foreground code, the background one is 10 more
+fn color(value: &str) -> Result<u8, String> {
+    let (bright, name) = match value.strip_prefix("bright-") {
+        Some(name) => (true, name),
+        None => (false, value),
+    };
+    match COLORS.iter().position(|&color| color == name) {
+        Some(n) if bright => Ok(90 + n as u8),
+        Some(n) => Ok(30 + n as u8),
+        None if value == "default" => Ok(39),
+        None => Err(format!("unknown color {}", value)),
+    }
+}
+
+// the colors things are drawn in, as ansi foreground codes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Theme {
+    pub keyword: u8,
+    pub types: u8,
+    pub string: u8,
+    pub number: u8,
+    pub comment: u8,
+    pub gutter: u8,
+    pub matches: u8, // drawn as the background of search matches
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            keyword: 33,
+            types: 32,
+            string: 35,
+            number: 31,
+            comment: 36,
+            gutter: 90, // bright black, so the numbers dont compete with the text
+            matches: 33,
+        }
+    }
+}
+
+impl Theme {
+    fn named(name: &str) -> Option<Theme> {
+        let default = Theme::default();
+        match name {
+            "default" => Some(default),
+            "bright" => Some(Theme {
+                keyword: 93,
+                types: 92,
+                string: 95,
+                number: 91,
+                comment: 96,
+                ..default
+            }),
+            // just the one color for all the text
+            "mono" => Some(Theme {
+                keyword: 39,
+                types: 39,
+                string: 39,
+                number: 39,
+                comment: 39,
+                ..default
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn highlight(&self, highlight: Highlight) -> u8 {
+        match highlight {
+            Highlight::Normal => 39,
+            Highlight::Keyword => self.keyword,
+            Highlight::Type => self.types,
+            Highlight::String => self.string,
+            Highlight::Number => self.number,
+            Highlight::Comment | Highlight::MultilineComment => self.comment,
+        }
+    }
+}
+
+// the things a key can be bound to. moving the cursor and typing arent in here, those keys
+// always do what they say
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    Save,
+    Find,
+    Replace,
+    Undo,
+    Redo,
+    Cut,
+    Copy,
+    Paste,
+    PasteOlder,
+    CutLine,
+    SetMark,
+    LineEndings,
+    Buffers,
+    NextBuffer,
+    PrevBuffer,
+    Windows,
+    LineNumbers,
+    Wrap,
+}
+
+const ACTIONS: [(&str, Action); 19] = [
+    ("quit", Action::Quit),
+    ("save", Action::Save),
+    ("find", Action::Find),
+    ("replace", Action::Replace),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("cut", Action::Cut),
+    ("copy", Action::Copy),
+    ("paste", Action::Paste),
+    ("paste_older", Action::PasteOlder),
+    ("cut_line", Action::CutLine),
+    ("set_mark", Action::SetMark),
+    ("line_endings", Action::LineEndings),
+    ("buffers", Action::Buffers),
+    ("next_buffer", Action::NextBuffer),
+    ("prev_buffer", Action::PrevBuffer),
+    ("windows", Action::Windows),
+    ("line_numbers", Action::LineNumbers),
+    ("wrap", Action::Wrap),
+];
+
+impl Action {
+    fn named(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, action)| action)
+    }
+}
+
+// which key does what, the config can add to these and take them away
+pub struct Keymap(Vec<(Key, Action)>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap(vec![
+            (Key::ctrl('q'), Action::Quit),
+            (Key::ctrl('s'), Action::Save),
+            (Key::ctrl('f'), Action::Find),
+            (Key::ctrl('r'), Action::Replace),
+            (Key::ctrl('z'), Action::Undo),
+            (Key::ctrl('y'), Action::Redo),
+            (Key::ctrl('x'), Action::Cut),
+            (Key::ctrl('c'), Action::Copy),
+            (Key::ctrl('v'), Action::Paste),
+            (Key::alt(KeyCode::Char('v')), Action::PasteOlder),
+            (Key::ctrl('k'), Action::CutLine),
+            (Key::ctrl(' '), Action::SetMark),
+            (Key::ctrl('e'), Action::LineEndings),
+            (Key::ctrl('b'), Action::Buffers),
+            (Key::alt(KeyCode::Right), Action::NextBuffer),
+            (Key::alt(KeyCode::Left), Action::PrevBuffer),
+            (Key::ctrl('w'), Action::Windows),
+            (Key::alt(KeyCode::Char('n')), Action::LineNumbers),
+            (Key::alt(KeyCode::Char('z')), Action::Wrap),
+        ])
+    }
+}
+
+impl Keymap {
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.0
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|&(_, action)| action)
+    }
+
+    // takes over the key from whatever it did before. new ones go first so the help shows them
+    fn bind(&mut self, key: Key, action: Option<Action>) {
+        self.0.retain(|(k, _)| *k != key);
+        if let Some(action) = action {
+            self.0.insert(0, (key, action));
+        }
+    }
+
+    fn key(&self, action: Action) -> Option<Key> {
+        self.0
+            .iter()
+            .find(|(_, a)| *a == action)
+            .map(|&(key, _)| key)
+    }
+
+    // the help message for the keys as they are bound, leaving out anything that has no key
+    pub fn help(&self) -> String {
+        let groups: [(&[Action], &str); 7] = [
+            (&[Action::Save], "to save"),
+            (&[Action::Quit], "to quit"),
+            (&[Action::Find], "to search"),
+            (&[Action::Undo, Action::Redo], "to undo/redo"),
+            (
+                &[Action::Cut, Action::Copy, Action::Paste],
+                "to cut/copy/paste",
+            ),
+            (&[Action::Buffers], "for buffers"),
+            (&[Action::Windows], "for windows"),
+        ];
+        let parts: Vec<String> = groups
+            .iter()
+            .filter_map(|(actions, what)| {
+                let keys: Option<Vec<String>> = actions
+                    .iter()
+                    .map(|&action| self.key(action).map(|key| key.to_string()))
+                    .collect();
+                Some(format!("{} {}", keys?.join("/"), what))
+            })
+            .collect();
+        format!("HELP: {}", parts.join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_valid_file() {
+        let config = Config::parse(
+            "tab_width = 4\n\
+             line_numbers = relative\n\
+             theme = mono\n\
+             quit_times = 2\n\
+             \n\
+             [keys]\n\
+             ctrl-g = find\n\
+             ctrl-f = none\n\
+             \n\
+             [colors]\n\
+             gutter = bright-red\n\
+             \n\
+             [filetype.markdown]\n\
+             wrap = words\n\
+             expand_tabs = true\n",
+        );
+        assert!(config.errors.is_empty(), "{:?}", config.errors);
+        assert_eq!(config.settings.tab_width, 4);
+        assert_eq!(config.line_numbers, LineNumbers::Relative);
+        assert_eq!(config.theme.keyword, 39);
+        assert_eq!(config.theme.gutter, 91);
+        assert_eq!(config.quit_times, 2);
+        assert_eq!(config.keymap.action(Key::ctrl('g')), Some(Action::Find));
+        assert_eq!(config.keymap.action(Key::ctrl('f')), None);
+        assert_eq!(config.keymap.action(Key::ctrl('s')), Some(Action::Save));
+
+        // the filetype starts from the plain settings and changes just what it says
+        let markdown = config.settings_for(Syntax::named("markdown"));
+        assert_eq!(markdown.tab_width, 4);
+        assert_eq!(markdown.wrap, Wrap::Words);
+        assert!(markdown.expand_tabs);
+        let rust = config.settings_for(Syntax::named("rust"));
+        assert_eq!(rust, config.settings);
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let config = Config::parse(
+            "tab_width = 4\n\
+             tabwidth = 4\n\
+             tab_width = 0\n\
+             wrap\n\
+             [filetype.cobol]\n\
+             [sideways]\n",
+        );
+        assert_eq!(
+            config.errors,
+            [
+                "line 2: unknown setting tabwidth",
+                "line 3: expected a number from 1 to 32, not 0",
+                "line 4: expected name = value",
+                "line 5: unknown filetype cobol",
+                "line 6: unknown section [sideways]",
+            ]
+        );
+        // the good lines still count
+        assert_eq!(config.settings.tab_width, 4);
+    }
+
+    #[test]
+    fn reports_bad_keys_and_actions() {
+        let config = Config::parse("[keys]\nhyper-x = save\nctrl-x = explode\nctrl-y = undo\n");
+        assert_eq!(
+            config.errors,
+            [
+                "line 2: unknown key hyper-x",
+                "line 3: unknown action explode"
+            ]
+        );
+        assert_eq!(config.keymap.action(Key::ctrl('x')), Some(Action::Cut));
+        assert_eq!(config.keymap.action(Key::ctrl('y')), Some(Action::Undo));
+    }
+
+    #[test]
+    fn comments_and_quotes() {
+        let config = Config::parse(
+            "# a whole line\n\
+             \x20  # an indented one\n\
+             theme = \"bright\" # after a quoted value\n\
+             quit_times = 5 # after a bare one\n\
+             [keys] # after a section\n\
+             \"#\" = save\n\
+             ctrl-# = quit\n\
+             \"ctrl-k\" = \"cut_line\"\n",
+        );
+        assert!(config.errors.is_empty(), "{:?}", config.errors);
+        assert_eq!(config.theme, Theme::named("bright").unwrap());
+        assert_eq!(config.quit_times, 5);
+        assert_eq!(
+            config.keymap.action(Key::new(KeyCode::Char('#'))),
+            Some(Action::Save)
+        );
+        assert_eq!(config.keymap.action(Key::ctrl('#')), Some(Action::Quit));
+
+        let config = Config::parse("theme = \"mono\" bright\ntheme = \"mono\ntheme = # nothing\n");
+        assert_eq!(
+            config.errors,
+            [
+                "line 1: unexpected bright at the end of the line",
+                "line 2: missing closing quote",
+                "line 3: expected default, bright or mono, not ",
+            ]
+        );
+    }
+}
@@ -1,107 +1,330 @@
 use std::{
-    fs::File,
-    io::{self, BufRead, BufReader, Read, Write, stdin, stdout},
+    borrow::Cow,
+    env,
+    io::{self, Write, stdout},
+    mem,
+    ops::Range,
     path::{Path, PathBuf},
-    time::{Duration, Instant},
+    process,
+    time::Instant,
 };
 
-use crate::{command, editor, keys, terminal};
+use crate::{
+    buffer::{Buffer, View},
+    command,
+    config::{Action, Config, Settings, Theme},
+    file::{self, LineEnding},
+    history::{Cursor, Edit, EditKind},
+    input::{Event, Input, Mouse, MouseKind},
+    keys::{Key, KeyCode, Modifiers},
+    kill_ring::KillRing,
+    piece_table::PieceTable,
+    regex,
+    search::{self, Pattern},
+    syntax::Highlight,
+    terminal,
+    unicode::{self, Unit},
+    window::{Layout, Rect, Window},
+};
 
-const MEGA_TAB_STOP: usize = 8;
-const MEGA_QUIT_TIMES: usize = 3;
+const MEGA_WHEEL_LINES: usize = 3;
+const MEGA_MAX_MATCHES: usize = 1000; // counted for the status bar, more than that shows as 1000+
 
 pub struct Editor {
-    rows: u16,
+    rows: u16, // the text rows and columns of the focused window
     cols: u16,
-    row_offset: usize,
-    col_offset: usize,
+    top: u16, // and where on the screen it is
+    left: u16,
+    area: Rect, // the whole screen except for the message bar
+    windows: Vec<Window>,
+    layout: Layout,
+    focused: usize,
+    separators: Vec<Rect>, // the columns between side by side windows
+    line_numbers: LineNumbers,
     buffer: Vec<u8>,
-    cx: u32,
-    cy: u32,
-    rx: u32,
-    lines: Vec<String>,
-    render: Vec<String>,
-    filename: Option<PathBuf>,
+    input: Input,
+    kill_ring: KillRing,
+    chain: Chain,
+    doc: Buffer,          // the buffer being edited
+    buffers: Vec<Buffer>, // all of them in order, with an empty stand in where doc came from
+    current: usize,       // where doc goes back in buffers
     status_msg: String,
     status_msg_time: Instant,
-    dirty: bool,
+    prompt_note: String, // shown after the answer while prompting, for modes and errors
+    search_options: search::Options, // toggled from the search prompt, kept for the next one
+    search: Option<Search>,
+    config: Config,
 }
 
 impl Editor {
     pub fn new() -> Self {
-        let size = terminal::size().expect("couldnt get size of terminal window");
-        Editor {
-            rows: size.1 - 2, // minus 1 for status bar, and minus 1 for message bar
-            cols: size.0,
-            row_offset: 0,
-            col_offset: 0,
+        let area = screen_area();
+        let config = Config::load();
+        let mut doc = Buffer::new();
+        doc.settings = config.settings_for(None);
+        let mut editor = Editor {
+            rows: 0,
+            cols: 0,
+            top: 0,
+            left: 0,
+            area,
+            windows: vec![Window {
+                buffer: 0,
+                view: View::default(),
+                rect: area,
+            }],
+            layout: Layout::Window(0),
+            focused: 0,
+            separators: Vec::new(),
+            line_numbers: config.line_numbers,
             buffer: Vec::new(),
-            cx: 0,
-            cy: 0,
-            rx: 0,
-            lines: Vec::new(),
-            render: Vec::new(),
-            filename: None,
+            input: Input::new(),
+            kill_ring: KillRing::new(),
+            chain: Chain::None,
+            doc,
+            buffers: vec![Buffer::new()],
+            current: 0,
             status_msg: String::new(),
             status_msg_time: Instant::now(),
-            dirty: false,
-        }
+            prompt_note: String::new(),
+            search_options: search::Options::default(),
+            search: None,
+            config,
+        };
+        editor.arrange();
+        editor
     }
 
     pub fn start(&mut self) {
-        self.set_status_message("HELP: Ctrl-S to save | Ctrl-Q to quit | Ctrl-F to search");
+        // a broken config gets in the way of the help, theres no telling what the keys do anyway.
+        // neither does a file that couldnt be opened, which open already put up
+        let message = match &self.config.errors[..] {
+            [] if !self.status_msg.is_empty() => self.status_msg.clone(),
+            [] => self.config.keymap.help(),
+            [error] => format!("Config {}", error),
+            [error, rest @ ..] => format!("Config {} (and {} more)", error, rest.len()),
+        };
+        self.set_status_message(&message);
         self.refresh_screen();
         self.process_keypress();
     }
-    pub fn open(&mut self, filename: PathBuf) -> io::Result<()> {
-        let file = File::open(&filename)?;
-        self.filename = Some(filename);
-
-        let bufread = BufReader::new(file);
-
-        let lines: Vec<String> = bufread.lines().flatten().collect();
-        self.render = lines
-            .clone()
-            .into_iter()
-            .map(|line| {
-                let mut idx: usize = 0;
-                line.chars()
-                    .map(|c| {
-                        if c == '\t' {
-                            let spaces_needed = (MEGA_TAB_STOP - (idx % MEGA_TAB_STOP)) as usize;
-                            idx += spaces_needed;
-                            " ".repeat(spaces_needed)
-                        } else {
-                            idx += 1;
-                            c.to_string()
-                        }
-                    })
-                    .collect()
+    // keys pressed before the editor was up, which get read before anything else
+    pub fn type_ahead(&mut self, bytes: &[u8]) {
+        self.input.type_ahead(bytes);
+    }
+    // the first file takes the place of the empty buffer the editor starts with, the rest go
+    // after it. one that doesnt exist yet starts out empty and gets made on save, anything else
+    // that goes wrong ends up in the status bar
+    pub fn open(&mut self, filename: PathBuf) {
+        let mut buffer = match Buffer::open(filename.clone()) {
+            Ok(buffer) => buffer,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                let mut buffer = Buffer::new();
+                buffer.set_filename(filename);
+                buffer
+            }
+            Err(error) => {
+                let message = format!("Cant open {}: {}", filename.display(), error);
+                self.set_status_message(&message);
+                return;
+            }
+        };
+        buffer.settings = self.config.settings_for(buffer.highlighter.syntax());
+        if self.doc.is_scratch() && self.buffers.len() == 1 {
+            self.doc = buffer;
+        } else {
+            self.buffers.push(buffer);
+        }
+    }
+    // every buffer in order, doc included
+    fn all_buffers(&self) -> impl Iterator<Item = &Buffer> {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| if i == self.current { &self.doc } else { buffer })
+    }
+    // makes buffer `index` the one being edited
+    fn switch_doc(&mut self, index: usize) {
+        if index != self.current {
+            mem::swap(&mut self.doc, &mut self.buffers[self.current]);
+            mem::swap(&mut self.doc, &mut self.buffers[index]);
+            self.current = index;
+        }
+    }
+    // asks which buffer to go to, by number or by part of the name
+    fn pick_buffer(&mut self) {
+        let list: Vec<String> = self
+            .all_buffers()
+            .enumerate()
+            .map(|(i, buffer)| {
+                let modified = if buffer.dirty { "*" } else { "" };
+                format!("{}:{}{}", i + 1, buffer.name(), modified)
             })
             .collect();
-        self.lines = lines;
-
-        return Ok(());
+        let mut answer = String::new();
+        let Some(answer) = self.prompt(
+            &format!("Buffer ({}):", list.join(" ")),
+            &mut answer,
+            false,
+            |_, _, _| {},
+        ) else {
+            return;
+        };
+        let answer = answer.trim();
+        let found: Vec<usize> = match answer.parse::<usize>() {
+            Ok(n) if (1..=self.buffers.len()).contains(&n) => vec![n - 1],
+            _ => self
+                .all_buffers()
+                .enumerate()
+                .filter(|(_, buffer)| buffer.name().contains(answer))
+                .map(|(i, _)| i)
+                .collect(),
+        };
+        match found[..] {
+            [index] => self.switch_buffer(index),
+            [] => self.set_status_message(&format!("No buffer matches <{}>", answer)),
+            _ => self.set_status_message(&format!("More than one buffer matches <{}>", answer)),
+        }
+    }
+    // shows buffer `index` in the focused window
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current || index >= self.buffers.len() {
+            return;
+        }
+        self.switch_doc(index);
+        self.windows[self.focused].buffer = index;
+        self.set_status_message(&format!(
+            "Buffer {} of {}: {}",
+            index + 1,
+            self.buffers.len(),
+            self.doc.name()
+        ));
     }
     fn process_keypress(&mut self) {
-        let mut quit_times = MEGA_QUIT_TIMES;
+        let mut quit_times = self.config.quit_times;
+
+        while let Some(event) = self.read_event() {
+            let chain = mem::replace(&mut self.chain, Chain::None);
+            let c = match event {
+                Event::Key(key) => key,
+                Event::Mouse(mouse) => {
+                    self.handle_mouse(mouse);
+                    self.refresh_screen();
+                    continue;
+                }
+                Event::Paste(bytes) => {
+                    self.delete_selection();
+                    self.paste(&bytes);
+                    quit_times = self.config.quit_times;
+                    self.refresh_screen();
+                    continue;
+                }
+            };
+            // shift+movement drags the selection along, plain movement drops it unless the mark
+            // was set, then it works like in emacs
+            if c.code.is_movement() {
+                if c.mods.contains(Modifiers::SHIFT) {
+                    if self.doc.anchor.is_none() {
+                        self.doc.anchor = Some((self.doc.cx, self.doc.cy));
+                    }
+                } else if !self.doc.mark_set {
+                    self.clear_selection();
+                }
+            }
 
-        while let Some(c) = self.read_key() {
-            match c {
-                c if c == Key::Char('q').control() => {
-                    if self.dirty && quit_times > 1 {
+            match self.config.keymap.action(c) {
+                Some(Action::Quit) => {
+                    let modified: Vec<_> = self
+                        .all_buffers()
+                        .filter(|buffer| buffer.dirty)
+                        .map(|buffer| buffer.name().into_owned())
+                        .collect();
+                    if !modified.is_empty() && quit_times > 1 {
                         quit_times -= 1;
-                        self.set_status_message(&format!("WARNING!!! File has unsaved changes. Press Ctrl-Q {} more time{} to quit.", quit_times, if quit_times  == 1 {""} else {"s"}));
+                        let files = match &modified[..] {
+                            [_] if self.buffers.len() == 1 => "File has".to_string(),
+                            [name] => format!("{} has", name),
+                            names => format!("{} have", names.join(", ")),
+                        };
+                        self.set_status_message(&format!(
+                            "WARNING!!! {} unsaved changes. Press {} {} more time{} to quit.",
+                            files,
+                            c,
+                            quit_times,
+                            if quit_times == 1 { "" } else { "s" }
+                        ));
                         self.refresh_screen();
                         continue;
                     }
                     break;
                 }
-                c if c == Key::Char('l').control() => {}
-                c if c == Key::Char('f').control() => {
+                Some(Action::Buffers) => {
+                    self.pick_buffer();
+                }
+                Some(Action::Windows) => {
+                    self.window_command();
+                }
+                Some(Action::LineNumbers) => {
+                    self.line_numbers = self.line_numbers.next();
+                    self.set_status_message(&format!("Line numbers: {}", self.line_numbers.name()));
+                }
+                Some(Action::Wrap) => {
+                    self.doc.settings.wrap = self.doc.settings.wrap.next();
+                    self.set_status_message(&format!("Wrap: {}", self.doc.settings.wrap.name()));
+                }
+                Some(Action::NextBuffer) => {
+                    self.switch_buffer((self.current + 1) % self.buffers.len());
+                }
+                Some(Action::PrevBuffer) => {
+                    let count = self.buffers.len();
+                    self.switch_buffer((self.current + count - 1) % count);
+                }
+                Some(Action::Find) => {
+                    self.clear_selection();
                     self.find();
                 }
-                c if c == Key::Char('s').control() => match self.save() {
+                Some(Action::Undo) => {
+                    self.clear_selection();
+                    self.undo();
+                }
+                Some(Action::Redo) => {
+                    self.clear_selection();
+                    self.redo();
+                }
+                Some(Action::Replace) => {
+                    self.clear_selection();
+                    self.replace();
+                }
+                Some(Action::LineEndings) => {
+                    self.clear_selection();
+                    self.convert_line_endings();
+                }
+                Some(Action::SetMark) => {
+                    if self.doc.mark_set {
+                        self.clear_selection();
+                        self.set_status_message("Mark cleared");
+                    } else {
+                        self.doc.anchor = Some((self.doc.cx, self.doc.cy));
+                        self.doc.mark_set = true;
+                        self.set_status_message("Mark set");
+                    }
+                }
+                Some(Action::Copy) => {
+                    self.copy();
+                }
+                Some(Action::Cut) => {
+                    self.cut(chain);
+                }
+                Some(Action::CutLine) => {
+                    self.cut_line(chain);
+                }
+                Some(Action::Paste) => {
+                    self.yank();
+                }
+                Some(Action::PasteOlder) => {
+                    self.yank_older(chain);
+                }
+                Some(Action::Save) => match self.save() {
                     Ok(len) => {
                         if len > 0 {
                             self.set_status_message(&format!("{} bytes written to disk", len));
@@ -113,121 +336,256 @@ impl Editor {
                         self.set_status_message(&format!("Can't save! IO error: {}", error));
                     }
                 },
-                Key::Special(EscapeSeq::UpArrow)
-                | Key::Special(EscapeSeq::DownArrow)
-                | Key::Special(EscapeSeq::RightArrow)
-                | Key::Special(EscapeSeq::LeftArrow) => self.move_cursor(c),
-                Key::Special(EscapeSeq::Home) => {
-                    self.cx = 0;
-                }
+                None => match c {
+                    c if c == Key::ctrl('l') => {}
+                    Key {
+                        code: KeyCode::Up | KeyCode::Down | KeyCode::Right | KeyCode::Left,
+                        ..
+                    } => self.move_cursor(c.code),
+                    Key {
+                        code: KeyCode::Home,
+                        ..
+                    } => {
+                        self.doc.cx = 0;
+                    }
 
-                Key::Special(EscapeSeq::End) => {
-                    if self.cy < self.lines.len() as u32 {
-                        self.cx = (&self.lines[self.cy as usize].len() - 1) as u32;
+                    Key {
+                        code: KeyCode::End, ..
+                    } if (self.doc.cy as usize) < self.doc.text.line_count() => {
+                        self.doc.cx = self.doc.text.line_len(self.doc.cy as usize) as u32;
                     }
-                }
-                Key::Special(EscapeSeq::PageUp) => {
-                    self.cy = self.row_offset as u32;
-                    for _ in 0..self.rows {
-                        self.move_cursor(Key::Special(EscapeSeq::UpArrow));
+                    Key {
+                        code: KeyCode::PageUp,
+                        ..
+                    } => {
+                        if self.doc.settings.wrap != Wrap::Off {
+                            let (_, col) = self.cursor_row();
+                            self.move_to_row((self.doc.row_offset, self.doc.wrap_offset), col);
+                        } else {
+                            self.doc.cy = self.doc.row_offset as u32;
+                        }
+                        for _ in 0..self.rows {
+                            self.move_cursor(KeyCode::Up);
+                        }
                     }
-                }
-                Key::Special(EscapeSeq::PageDown) => {
-                    self.cy = (self.row_offset as u32) + (self.rows as u32) - 1;
-                    if (self.cy as usize) > self.lines.len() {
-                        self.cy = self.lines.len() as u32;
+                    Key {
+                        code: KeyCode::PageDown,
+                        ..
+                    } => {
+                        if self.doc.settings.wrap != Wrap::Off {
+                            let (_, col) = self.cursor_row();
+                            let top = (self.doc.row_offset, self.doc.wrap_offset);
+                            self.move_to_row(
+                                self.rows_down(top, self.rows as usize - 1, true),
+                                col,
+                            );
+                        } else {
+                            self.doc.cy = (self.doc.row_offset as u32) + (self.rows as u32) - 1;
+                            if (self.doc.cy as usize) > self.doc.text.line_count() {
+                                self.doc.cy = self.doc.text.line_count() as u32;
+                            }
+                        }
+                        for _ in 0..self.rows {
+                            self.move_cursor(KeyCode::Down);
+                        }
                     }
-                    for _ in 0..self.rows {
-                        self.move_cursor(Key::Special(EscapeSeq::DownArrow));
+                    Key {
+                        code: KeyCode::Backspace,
+                        ..
+                    } => {
+                        self.backspace_char();
                     }
-                }
-                Key::Char(keys::BACKSPACE) => {
-                    self.backspace_char();
-                }
-                Key::Special(EscapeSeq::Delete) => {
-                    self.move_cursor(Key::Special(EscapeSeq::RightArrow));
-                    self.convert_cx_to_rx(); // move cursor only does cx, we need rx also
-                    self.backspace_char();
-                }
-                c if c == Key::Char('h').control() => {
-                    self.backspace_char();
-                }
-                Key::Char(keys::ENTER) => {
-                    self.insert_newline();
-                }
-                Key::Char(c) => {
-                    self.insert_char(c);
-                }
-                _ => {}
+                    Key {
+                        code: KeyCode::Delete,
+                        ..
+                    } => {
+                        self.delete_char();
+                    }
+                    // legacy terminals send ctrl-h for backspace, with the kitty protocol its free
+                    c if c == Key::ctrl('h') && !terminal::kitty_keyboard() => {
+                        self.backspace_char();
+                    }
+                    Key {
+                        code: KeyCode::Enter,
+                        ..
+                    } => {
+                        self.delete_selection();
+                        self.insert_newline();
+                    }
+                    Key {
+                        code: KeyCode::Tab,
+                        mods: Modifiers::NONE,
+                    } => {
+                        if self.selection().is_some() {
+                            self.indent(false);
+                        } else {
+                            self.insert_tab();
+                        }
+                    }
+                    Key {
+                        code: KeyCode::Tab,
+                        mods: Modifiers::SHIFT,
+                    } => {
+                        self.indent(true);
+                    }
+                    Key {
+                        code: KeyCode::Char(c),
+                        mods,
+                    } if !mods.contains(Modifiers::CTRL) && !mods.contains(Modifiers::ALT) => {
+                        self.delete_selection();
+                        self.insert_char(c);
+                    }
+                    _ => {}
+                },
             }
 
             //user pressed other key, reset quit_times
-            quit_times = MEGA_QUIT_TIMES;
+            quit_times = self.config.quit_times;
 
             self.refresh_screen();
         }
     }
-    fn read_key(&self) -> Option<editor::Key> {
-        let mut buf = [0u8; 1];
-
-        while stdin().read(&mut buf).ok()? == 0 {} //spin if just times out
-
-        if buf[0] == b'\x1b' {
-            let mut escape_code = [0u8; 3];
-            stdin().read(&mut escape_code).ok()?;
-            match escape_code[0] {
-                b'[' => match escape_code[1] {
-                    b'A' => return Some(Key::Special(EscapeSeq::UpArrow)),
-                    b'B' => return Some(Key::Special(EscapeSeq::DownArrow)),
-                    b'C' => return Some(Key::Special(EscapeSeq::RightArrow)),
-                    b'D' => return Some(Key::Special(EscapeSeq::LeftArrow)),
-                    b'H' => return Some(Key::Special(EscapeSeq::Home)),
-                    b'F' => return Some(Key::Special(EscapeSeq::End)),
-                    c => {
-                        if escape_code[2] == b'~' {
-                            match c {
-                                b'1' => return Some(Key::Special(EscapeSeq::Home)),
-                                b'3' => return Some(Key::Special(EscapeSeq::Delete)),
-                                b'4' => return Some(Key::Special(EscapeSeq::End)),
-                                b'5' => return Some(Key::Special(EscapeSeq::PageUp)),
-                                b'6' => return Some(Key::Special(EscapeSeq::PageDown)),
-                                b'7' => return Some(Key::Special(EscapeSeq::Home)),
-                                b'8' => return Some(Key::Special(EscapeSeq::End)),
-                                _ => {}
-                            }
-                        }
-                    }
-                },
-                b'O' => match escape_code[1] {
-                    b'H' => return Some(Key::Special(EscapeSeq::Home)),
-                    b'F' => return Some(Key::Special(EscapeSeq::End)),
-                    _ => {}
-                },
-                _ => {}
+    // keys only, for prompts and the like where the mouse has nothing to do
+    fn read_key(&mut self) -> Option<Key> {
+        loop {
+            if let Event::Key(key) = self.read_event()? {
+                return Some(key);
+            }
+        }
+    }
+    fn read_event(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.input.read_event().ok()? {
+                return Some(event);
+            }
+            // nothing pressed for a bit, check whether anything else needs doing
+            if let Some(signal) = terminal::fatal_signal() {
+                terminal::restore();
+                self.write_recovery();
+                process::exit(128 + signal);
+            }
+            if terminal::resized() {
+                self.resize();
+                self.refresh_screen();
             }
-            return Some(Key::Special(EscapeSeq::Escape));
         }
-        return Some(Key::Char(buf[0] as char));
     }
     fn draw_rows(&mut self) {
+        let gutter = self.gutter_width() as usize;
+        let segments = self.segments();
         for row in 0..self.rows {
-            let line_in_file = row as usize + self.row_offset;
-
-            if line_in_file < self.render.len() {
+            self.buffer.extend_from_slice(&command::move_cursor(
+                (self.top + row + 1) as u32,
+                (self.left + 1) as u32,
+            ));
+
+            if let Some(&Segment {
+                line: line_in_file,
+                start,
+                end,
+                first,
+            }) = segments.get(row as usize)
+            {
                 //we still have lines to print
-                let line = &self.render[line_in_file];
-                let len: isize = (line.len() as isize) - (self.col_offset as isize);
-
-                let len = (len.max(0) as usize).min((self.cols - 1) as usize);
-                if len > 0 {
-                    // need this because even if the range below is 0, the compiler will
-                    // still try to index from offset to offset == nothing, but that doesnt exist
-                    // in the string which is why the len is 0
-                    self.buffer.extend_from_slice(
-                        &line[self.col_offset..self.col_offset + len].as_bytes(),
-                    );
-                }
-            } else if self.lines.len() == 0 && row == self.rows / 3 {
+                if gutter > 0 && !first {
+                    // the rest of a wrapped line, the number is on the row it starts on
+                    self.buffer.extend_from_slice(" ".repeat(gutter).as_bytes());
+                } else if gutter > 0 {
+                    let distance = line_in_file.abs_diff(self.doc.cy as usize);
+                    let number = match self.line_numbers {
+                        LineNumbers::Relative => distance,
+                        LineNumbers::Hybrid if distance != 0 => distance,
+                        _ => line_in_file + 1,
+                    };
+                    self.buffer
+                        .extend_from_slice(&command::set_foreground(self.config.theme.gutter));
+                    self.buffer
+                        .extend_from_slice(format!("{:>1$} ", number, gutter - 1).as_bytes());
+                    self.buffer.extend_from_slice(command::DEFAULT_FOREGROUND);
+                }
+                let line = self.doc.text.line_bytes(line_in_file);
+                let hl = self.doc.highlighter.line(&self.doc.text, line_in_file);
+                let selected = self
+                    .selection()
+                    .map(|(from, to)| selected_bytes(line_in_file, from, to));
+                let matches = self.search_matches(line_in_file);
+
+                let mut current = Highlight::Normal;
+                let mut style = Style::Plain;
+                let mut rx = 0;
+                let mut clipped = false;
+                for (i, unit) in unicode::units(&line) {
+                    let (text, width) = render_unit(unit, rx, self.doc.settings.tab_width);
+                    if rx + width > end {
+                        clipped = true;
+                        break; // doesnt fit, dont let a wide char spill past the edge
+                    }
+                    let visible = rx + width > start;
+                    let unit_style = if selected.as_ref().is_some_and(|range| range.contains(&i)) {
+                        Style::Selected
+                    } else {
+                        match matches.iter().find(|(range, _)| range.contains(&i)) {
+                            Some((_, true)) => Style::CurrentMatch,
+                            Some((_, false)) => Style::Match,
+                            None => Style::Plain,
+                        }
+                    };
+                    if visible && unit_style != style {
+                        style = unit_style;
+                        self.buffer.extend_from_slice(command::NORMAL_COLORS);
+                        self.buffer
+                            .extend_from_slice(&style.colors(&self.config.theme));
+                        current = Highlight::Normal;
+                    }
+                    if rx < start {
+                        // a wide char or tab cut in half by the left edge, pad out the part we see
+                        for _ in start.min(rx + width)..rx + width {
+                            self.buffer.push(b' ');
+                        }
+                        rx += width;
+                        continue;
+                    }
+                    rx += width;
+
+                    // only send a color when it changes, not for every char
+                    if hl[i] != current {
+                        current = hl[i];
+                        self.buffer.extend_from_slice(&command::set_foreground(
+                            self.config.theme.highlight(current),
+                        ));
+                    }
+                    let special = match unit {
+                        Unit::Grapheme(g) => g != "\t" && g.starts_with(char::is_control),
+                        Unit::Invalid(_) => true,
+                    };
+                    if special && style == Style::Plain {
+                        self.buffer.extend_from_slice(command::INVERTED_COLORS);
+                        self.buffer.extend_from_slice(text.as_bytes());
+                        self.buffer.extend_from_slice(command::NORMAL_COLORS);
+                        current = Highlight::Normal;
+                    } else {
+                        self.buffer.extend_from_slice(text.as_bytes());
+                    }
+                }
+                // a selected newline shows as one selected space past the end of the line
+                let newline_selected = selected
+                    .as_ref()
+                    .is_some_and(|range| range.contains(&line.len()));
+                if newline_selected && !clipped && rx >= start {
+                    if style != Style::Selected {
+                        style = Style::Selected;
+                        self.buffer.extend_from_slice(command::NORMAL_COLORS);
+                        self.buffer
+                            .extend_from_slice(&style.colors(&self.config.theme));
+                    }
+                    self.buffer.push(b' ');
+                }
+                if style != Style::Plain {
+                    self.buffer.extend_from_slice(command::NORMAL_COLORS);
+                } else if current != Highlight::Normal {
+                    self.buffer.extend_from_slice(command::DEFAULT_FOREGROUND);
+                }
+            } else if self.doc.text.is_empty() && row == self.rows / 3 {
                 let msg = format!("Mega editor -- version {}", env!("CARGO_PKG_VERSION"));
                 let msg_len = if msg.len() <= self.cols as usize {
                     msg.len()
@@ -244,85 +602,363 @@ impl Editor {
                 for _ in 0..msg_padding {
                     self.buffer.push(b' ');
                 }
-                self.buffer.extend_from_slice(&msg[0..msg_len].as_bytes());
+                self.buffer.extend_from_slice(&msg.as_bytes()[0..msg_len]);
             } else {
                 self.buffer.push(b'~');
             }
 
+            // anything to the right gets drawn again after this, so it doesnt matter what is wiped
             self.buffer.extend_from_slice(command::CLEAR_REST_OF_LINE);
-
-            self.buffer.extend_from_slice(b"\r\n");
         }
     }
     fn draw_status_bar(&mut self) {
+        self.buffer.extend_from_slice(&command::move_cursor(
+            (self.top + self.rows + 1) as u32,
+            (self.left + 1) as u32,
+        ));
         self.buffer.extend_from_slice(command::INVERTED_COLORS);
-        let modified = if self.dirty { "(modified)" } else { "" };
+        let modified = if self.doc.dirty { "(modified)" } else { "" };
+        let invalid = if self.doc.invalid_utf8 {
+            " [not valid UTF-8]"
+        } else {
+            ""
+        };
 
-        let status = match &self.filename {
+        let status = match &self.doc.filename {
             Some(filename) => match filename.to_str() {
                 Some(filename) => {
-                    format!("{} {} - {} lines", filename, modified, self.rows)
+                    format!("{} {}{} - {} lines", filename, modified, invalid, self.rows)
                 }
-                None => format!("[Non-Unicode file name] {} - {} lines", modified, self.rows),
+                None => format!(
+                    "[Non-Unicode file name] {}{} - {} lines",
+                    modified, invalid, self.rows
+                ),
             },
             None => {
                 format!("[No Name] - {} lines", self.rows)
             }
         };
+        // which buffer this is, once there is more than one
+        let status = if self.buffers.len() > 1 {
+            format!("[{}/{}] {}", self.current + 1, self.buffers.len(), status)
+        } else {
+            status
+        };
         let len = status.len().min(self.cols as usize);
-        self.buffer.extend_from_slice(&status[0..len].as_bytes());
+        self.buffer.extend_from_slice(&status.as_bytes()[0..len]);
 
         // line number
-        let right_status = format!("{}/{}", self.cy + 1, self.lines.len());
-
-        for _ in len..(self.cols as usize) - right_status.len() {
+        let filetype = self
+            .doc
+            .highlighter
+            .syntax()
+            .map_or("no ft", |syntax| syntax.name);
+        let line_ending = format!(
+            "{}{}",
+            self.doc.line_ending.name(),
+            if self.doc.final_newline { "" } else { " noeol" }
+        );
+        let right_status = format!(
+            "{} | {} | {}/{}",
+            filetype,
+            line_ending,
+            self.doc.cy + 1,
+            self.doc.text.line_count()
+        );
+
+        // the right side is dropped rather than wrapped when the window is too narrow for both
+        let fits = len + right_status.len() <= self.cols as usize;
+        let pad_to = if fits {
+            self.cols as usize - right_status.len()
+        } else {
+            self.cols as usize
+        };
+        for _ in len..pad_to {
             self.buffer.push(b' ');
         }
-        self.buffer.extend_from_slice(right_status.as_bytes());
+        if fits {
+            self.buffer.extend_from_slice(right_status.as_bytes());
+        }
         self.buffer.extend_from_slice(command::NORMAL_COLORS);
-        self.buffer.extend_from_slice(b"\r\n");
+    }
+    fn draw_separators(&mut self) {
+        for separator in &self.separators {
+            for row in 0..separator.rows {
+                self.buffer.extend_from_slice(&command::move_cursor(
+                    (separator.top + row + 1) as u32,
+                    (separator.left + 1) as u32,
+                ));
+                self.buffer.push(b'|');
+            }
+        }
     }
     fn draw_message_bar(&mut self) {
+        self.buffer
+            .extend_from_slice(&command::move_cursor((self.area.rows + 1) as u32, 1));
         self.buffer.extend_from_slice(command::CLEAR_REST_OF_LINE);
-        if self.status_msg_time.elapsed() < Duration::new(5, 0) {
-            self.buffer.extend_from_slice(self.status_msg.as_bytes());
+        if self.status_msg_time.elapsed() < self.config.message_timeout {
+            let len = self.status_msg.floor_char_boundary(self.area.cols as usize);
+            self.buffer
+                .extend_from_slice(&self.status_msg.as_bytes()[..len]);
+        }
+    }
+    // called once a SIGWINCH comes in, the next refresh draws everything at the new size
+    fn resize(&mut self) {
+        self.area = screen_area();
+        self.arrange();
+        self.scroll(); // pulls the offsets back so the cursor stays on screen
+    }
+    // works out where every window goes, and takes the size of the focused one
+    fn arrange(&mut self) {
+        self.separators = self.layout.arrange(self.area, &mut self.windows);
+        self.use_rect(self.windows[self.focused].rect);
+    }
+    fn use_rect(&mut self, rect: Rect) {
+        (self.top, self.left) = (rect.top, rect.left);
+        // the last row is the status line
+        self.rows = rect.rows.saturating_sub(1).max(1);
+        self.cols = rect.cols.max(1);
+    }
+    // columns for the line numbers, enough for the last line and a space. none when they are off,
+    // or when the window is so narrow there would be next to nothing left for the text
+    fn gutter_width(&self) -> u16 {
+        if self.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        let width = self.doc.text.line_count().to_string().len() as u16 + 1;
+        if width + 2 > self.cols { 0 } else { width }
+    }
+    // what is left of the window for the text once the gutter has its share
+    fn text_cols(&self) -> u16 {
+        self.cols - self.gutter_width()
+    }
+    // the width lines are wrapped at, one short of the window so the cursor has somewhere to go
+    // at the end of a full row, same as when scrolling sideways
+    fn wrap_width(&self) -> usize {
+        (self.text_cols() as usize).saturating_sub(1).max(1)
+    }
+    // where each row of line y starts when it is wrapped, just the one row when it isnt
+    fn wrap_points(&self, y: usize) -> Vec<(usize, usize)> {
+        if self.doc.settings.wrap == Wrap::Off || y >= self.doc.text.line_count() {
+            return vec![(0, 0)];
+        }
+        let line = self.doc.text.line_bytes(y);
+        wrap_points(&line, self.wrap_width(), self.doc.settings)
+    }
+    // screen rows go by (line, row of that line), these step through them. the line after the
+    // last one is there to go to, it has one empty row
+    fn row_above(&self, (y, row): (usize, usize)) -> Option<(usize, usize)> {
+        if row > 0 {
+            Some((y, row - 1))
+        } else if y > 0 {
+            Some((y - 1, self.wrap_points(y - 1).len() - 1))
+        } else {
+            None
+        }
+    }
+    fn row_below(&self, (y, row): (usize, usize)) -> Option<(usize, usize)> {
+        if row + 1 < self.wrap_points(y).len() {
+            Some((y, row + 1))
+        } else if y < self.doc.text.line_count() {
+            Some((y + 1, 0))
+        } else {
+            None
+        }
+    }
+    // n rows further down, or as far as the last line goes. or the line after it with past_end,
+    // which is where the cursor can go but the window cant start
+    fn rows_down(&self, mut pos: (usize, usize), n: usize, past_end: bool) -> (usize, usize) {
+        let lines = self.doc.text.line_count() + past_end as usize;
+        for _ in 0..n {
+            match self.row_below(pos) {
+                Some(below) if below.0 < lines => pos = below,
+                _ => break,
+            }
+        }
+        pos
+    }
+    fn rows_up(&self, mut pos: (usize, usize), n: usize) -> (usize, usize) {
+        for _ in 0..n {
+            match self.row_above(pos) {
+                Some(above) => pos = above,
+                None => break,
+            }
+        }
+        pos
+    }
+    // the row the cursor is on, and how far along that row it is
+    fn cursor_row(&mut self) -> ((usize, usize), usize) {
+        self.convert_cx_to_rx();
+        let points = self.wrap_points(self.doc.cy as usize);
+        let row = wrap_row(&points, self.doc.cx as usize);
+        let col = self.doc.rx as usize - points[row].1;
+        ((self.doc.cy as usize, row), col)
+    }
+    // puts the cursor col along the row, or on the last char of it if the row is shorter than
+    // that. going on to the end would put it at the start of the next row
+    fn move_to_row(&mut self, (y, row): (usize, usize), col: usize) {
+        self.doc.cy = y as u32;
+        if y >= self.doc.text.line_count() {
+            self.doc.cx = 0;
+            return;
+        }
+        let points = self.wrap_points(y);
+        let mut cx = self.rx_to_cx(y, points[row].1 + col);
+        if let Some(&(next, _)) = points.get(row + 1)
+            && cx >= next
+        {
+            cx = unicode::prev_boundary(&self.doc.text.line_bytes(y), next);
+        }
+        self.doc.cx = cx as u32;
+    }
+    // up or down a row on the screen when wrapping, which might be in the same line
+    fn move_row(&mut self, up: bool) {
+        let (pos, col) = self.cursor_row();
+        let next = if up {
+            self.row_above(pos)
+        } else {
+            self.row_below(pos)
+        };
+        if let Some(pos) = next {
+            self.move_to_row(pos, col);
+        }
+    }
+    // what goes on each row of the window
+    fn segments(&self) -> Vec<Segment> {
+        let rows = self.rows as usize;
+        let lines = self.doc.text.line_count();
+        if self.doc.settings.wrap == Wrap::Off {
+            let start = self.doc.col_offset;
+            let end = start + (self.text_cols() - 1) as usize;
+            return (self.doc.row_offset..lines.min(self.doc.row_offset + rows))
+                .map(|line| Segment {
+                    line,
+                    start,
+                    end,
+                    first: true,
+                })
+                .collect();
         }
+        let mut segments = Vec::new();
+        let mut skip = self.doc.wrap_offset;
+        for line in self.doc.row_offset..lines {
+            let points = self.wrap_points(line);
+            for (row, &(_, start)) in points.iter().enumerate().skip(skip) {
+                if segments.len() == rows {
+                    return segments;
+                }
+                let end = points
+                    .get(row + 1)
+                    .map_or(start + self.wrap_width(), |&(_, next)| next);
+                segments.push(Segment {
+                    line,
+                    start,
+                    end,
+                    first: row == 0,
+                });
+            }
+            skip = 0;
+        }
+        segments
     }
     fn scroll(&mut self) {
         self.convert_cx_to_rx();
+        if self.doc.settings.wrap != Wrap::Off {
+            self.scroll_wrapped();
+            return;
+        }
+        self.doc.wrap_offset = 0;
 
-        if (self.cy as usize) < self.row_offset {
-            self.row_offset = self.cy as usize;
+        if (self.doc.cy as usize) < self.doc.row_offset {
+            self.doc.row_offset = self.doc.cy as usize;
         }
-        if self.cy as usize >= self.row_offset as usize + self.rows as usize {
+        if self.doc.cy as usize >= self.doc.row_offset + self.rows as usize {
             // technically, i think we can just add one to the offset because we are only moving
             // one step at a time, but i think we do this bc we will scroll with page up and down,
             // and that could be more than just 1 step at a time, so recalc based on cursor pointer
             // in the file
-            self.row_offset = self.cy as usize - self.rows as usize + 1;
+            self.doc.row_offset = self.doc.cy as usize - self.rows as usize + 1;
         }
 
-        if (self.rx as usize) < self.col_offset {
-            self.col_offset = self.rx as usize;
+        if (self.doc.rx as usize) < self.doc.col_offset {
+            self.doc.col_offset = self.doc.rx as usize;
         }
-        if self.rx as usize >= self.cols as usize + self.col_offset {
-            self.col_offset = self.rx as usize - self.cols as usize + 1;
+        let text_cols = self.text_cols() as usize;
+        if self.doc.rx as usize >= text_cols + self.doc.col_offset {
+            self.doc.col_offset = self.doc.rx as usize - text_cols + 1;
         }
     }
 
+    // like scroll but by rows on the screen, so the top can be part way through a long line.
+    // nothing goes sideways, the lines fit across the window
+    fn scroll_wrapped(&mut self) {
+        self.doc.col_offset = 0;
+        let top_rows = self.wrap_points(self.doc.row_offset).len();
+        self.doc.wrap_offset = self.doc.wrap_offset.min(top_rows - 1);
+
+        let top = (self.doc.row_offset, self.doc.wrap_offset);
+        let (cursor, _) = self.cursor_row();
+        if cursor < top {
+            (self.doc.row_offset, self.doc.wrap_offset) = cursor;
+            return;
+        }
+        // walk up from the cursor, if the top isnt within a window of it the cursor goes on the
+        // last row
+        let mut pos = cursor;
+        for _ in 1..self.rows {
+            if pos == top {
+                return;
+            }
+            match self.row_above(pos) {
+                Some(above) => pos = above,
+                None => return,
+            }
+        }
+        if top < pos {
+            (self.doc.row_offset, self.doc.wrap_offset) = pos;
+        }
+    }
+    // where the cursor is in the window
+    fn screen_cursor(&mut self) -> (usize, usize) {
+        if self.doc.settings.wrap == Wrap::Off {
+            return (
+                self.doc.cy as usize - self.doc.row_offset,
+                self.doc.rx as usize - self.doc.col_offset,
+            );
+        }
+        let ((y, row), col) = self.cursor_row();
+        let above: usize = (self.doc.row_offset..y)
+            .map(|line| self.wrap_points(line).len())
+            .sum();
+        (above + row - self.doc.wrap_offset, col)
+    }
+
     fn refresh_screen(&mut self) {
         self.scroll();
 
         self.buffer.extend_from_slice(command::HIDE_CURSOR);
-        self.buffer.extend_from_slice(command::MOVE_CURSOR_TOP_LEFT);
 
-        self.draw_rows();
-        self.draw_status_bar();
+        // left to right, so clearing to the end of a row never wipes out a window already drawn
+        let mut order: Vec<usize> = (0..self.windows.len()).collect();
+        order.sort_by_key(|&window| {
+            let rect = self.windows[window].rect;
+            (rect.left, rect.top)
+        });
+        for window in order {
+            if window == self.focused {
+                self.draw_rows();
+                self.draw_status_bar();
+            } else {
+                self.draw_other_window(window);
+            }
+        }
+        self.draw_separators();
         self.draw_message_bar();
 
-        self.buffer.extend_from_slice(command::move_cursor(
-            (self.cy as usize - self.row_offset + 1) as u32,
-            (self.rx as usize - self.col_offset + 1) as u32,
+        let (row, col) = self.screen_cursor();
+        self.buffer.extend_from_slice(&command::move_cursor(
+            (self.top as usize + row + 1) as u32,
+            (self.left + self.gutter_width()) as u32 + (col + 1) as u32,
         ));
         self.buffer.extend_from_slice(command::SHOW_CURSOR);
 
@@ -331,164 +967,737 @@ impl Editor {
         stdout().flush().unwrap()
     }
 
-    fn move_cursor(&mut self, key: Key) {
+    // draws a window without focus by making it look like it has focus for a moment
+    fn draw_other_window(&mut self, window: usize) {
+        let rect = self.windows[window].rect;
+        if rect.rows < 2 || rect.cols == 0 {
+            return; // squeezed out, theres no room for it
+        }
+        let focused = self.doc.view();
+        let current = self.current;
+        let search = self.search.take(); // only the focused window shows the matches
+
+        self.switch_doc(self.windows[window].buffer);
+        self.doc.set_view(self.windows[window].view);
+        self.use_rect(rect);
+        self.scroll();
+        self.draw_rows();
+        self.draw_status_bar();
+        self.windows[window].view = self.doc.view();
+
+        self.switch_doc(current);
+        self.doc.set_view(focused);
+        self.use_rect(self.windows[self.focused].rect);
+        self.search = search;
+    }
+    // ctrl-w and then a key for what to do with the windows, like in vim
+    fn window_command(&mut self) {
+        self.set_status_message(
+            "Window: (s)plit (v)ertical split (c)lose (w) next window (+/-) bigger/smaller",
+        );
+        self.refresh_screen();
+        let Some(key) = self.read_key() else {
+            return;
+        };
+        self.set_status_message("");
+        match key.code {
+            KeyCode::Char('s') => self.split_window(false),
+            KeyCode::Char('v') => self.split_window(true),
+            KeyCode::Char('c' | 'q') => self.close_window(),
+            KeyCode::Char('w') | KeyCode::Tab => self.cycle_window(),
+            KeyCode::Char('+') => self.resize_window(1),
+            KeyCode::Char('-') => self.resize_window(-1),
+            _ => {}
+        }
+    }
+    // the new window shows the same buffer from the same place, and focus stays where it was
+    fn split_window(&mut self, vertical: bool) {
+        let rect = self.windows[self.focused].rect;
+        let room = if vertical {
+            rect.cols >= 3
+        } else {
+            rect.rows >= 4
+        };
+        if !room {
+            self.set_status_message("Not enough room to split");
+            return;
+        }
+        let new = self.windows.len();
+        self.windows.push(Window {
+            buffer: self.current,
+            view: self.doc.view(),
+            rect,
+        });
+        self.layout.split(self.focused, new, vertical);
+        self.arrange();
+    }
+    fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.set_status_message("Cant close the only window");
+            return;
+        }
+        let closing = self.focused;
+        self.cycle_window();
+        self.windows.remove(closing);
+        self.layout.remove(closing);
+        if self.focused > closing {
+            self.focused -= 1;
+        }
+        self.arrange();
+    }
+    fn cycle_window(&mut self) {
+        let order = self.layout.order();
+        let at = order.iter().position(|&w| w == self.focused).unwrap_or(0);
+        self.focus_window(order[(at + 1) % order.len()]);
+    }
+    fn focus_window(&mut self, window: usize) {
+        if window == self.focused {
+            return;
+        }
+        self.windows[self.focused].view = self.doc.view();
+        self.focused = window;
+        self.switch_doc(self.windows[window].buffer);
+        self.doc.set_view(self.windows[window].view);
+        self.use_rect(self.windows[window].rect);
+    }
+    fn resize_window(&mut self, delta: i16) {
+        if self.layout.resize(self.focused, delta) {
+            self.arrange();
+        } else {
+            self.set_status_message("Theres only the one window");
+        }
+    }
+    fn window_at(&self, row: u16, col: u16) -> Option<usize> {
+        self.windows.iter().position(|window| {
+            let rect = window.rect;
+            (rect.top..rect.top + rect.rows).contains(&row)
+                && (rect.left..rect.left + rect.cols).contains(&col)
+        })
+    }
+    fn move_cursor(&mut self, key: KeyCode) {
         // cy, could be one more than the file,
         // and if so there is not a corresponding line in lines, so will panic
 
         match key {
-            Key::Special(EscapeSeq::UpArrow) => {
-                if self.cy > 0 {
-                    self.cy -= 1
+            KeyCode::Up | KeyCode::Down if self.doc.settings.wrap != Wrap::Off => {
+                self.move_row(key == KeyCode::Up);
+            }
+            KeyCode::Up => {
+                if self.doc.cy > 0 {
+                    self.move_to_line(self.doc.cy - 1);
                 }
             }
-            Key::Special(EscapeSeq::DownArrow) => {
-                if self.cy < self.lines.len() as u32 {
-                    self.cy += 1
+            KeyCode::Down => {
+                if (self.doc.cy as usize) < self.doc.text.line_count() {
+                    self.move_to_line(self.doc.cy + 1);
                 }
             }
-            Key::Special(EscapeSeq::RightArrow) => {
-                self.cx += 1;
-                let curr_row = self.lines.get(self.cy as usize); // can be one more than lines, so
-                // need to check
-                if let Some(curr_row) = curr_row {
-                    if (self.cx as usize) > curr_row.len() {
+            KeyCode::Right => {
+                // cy can be one more than lines, so need to check
+                if (self.doc.cy as usize) < self.doc.text.line_count() {
+                    let line = self.doc.text.line_bytes(self.doc.cy as usize);
+                    if (self.doc.cx as usize) < line.len() {
+                        // step over the whole grapheme, never into the middle of one
+                        self.doc.cx = unicode::next_boundary(&line, self.doc.cx as usize) as u32;
+                    } else {
                         //went right on position end, apparently want to wrap down a line
-                        // ie do not do this on the last line
-                        self.cy += 1;
-                        self.cx = 0;
+                        self.doc.cy += 1;
+                        self.doc.cx = 0;
                     }
                 }
             }
-            Key::Special(EscapeSeq::LeftArrow) => {
-                if self.cx > 0 {
-                    self.cx -= 1
+            KeyCode::Left => {
+                if self.doc.cx > 0 {
+                    let line = self.doc.text.line_bytes(self.doc.cy as usize);
+                    self.doc.cx = unicode::prev_boundary(&line, self.doc.cx as usize) as u32;
                 } else {
                     //went left on position 0, apparently want to wrap up a line
-                    if self.cy > 0 {
+                    if self.doc.cy > 0 {
                         // so no overflow
                         // do not do this on the first line
-                        let prev_row = &self.lines[self.cy as usize - 1];
-                        self.cy -= 1;
-                        self.cx = (prev_row.len()) as u32;
+                        self.doc.cy -= 1;
+                        self.doc.cx = self.doc.text.line_len(self.doc.cy as usize) as u32;
                     }
                 }
             }
             _ => panic!("this should not happen"),
         }
-        //snap to end of line
-        //need it down here bc may have adjusted the cy above
-        let curr_row = self.lines.get(self.cy as usize);
+    }
+    fn handle_mouse(&mut self, mouse: Mouse) {
+        // clicks and the wheel go to the window under the pointer, drags stay in the one they
+        // started in
+        if matches!(
+            mouse.kind,
+            MouseKind::Press | MouseKind::WheelUp | MouseKind::WheelDown
+        ) && let Some(window) = self.window_at(mouse.row, mouse.col)
+        {
+            self.focus_window(window);
+        }
+        let row = mouse.row.saturating_sub(self.top);
+        let col = mouse.col.saturating_sub(self.left);
+        match mouse.kind {
+            MouseKind::WheelUp | MouseKind::WheelDown if self.doc.settings.wrap != Wrap::Off => {
+                self.scroll_rows(mouse.kind == MouseKind::WheelUp);
+            }
+            MouseKind::WheelUp => {
+                self.doc.row_offset = self.doc.row_offset.saturating_sub(MEGA_WHEEL_LINES);
+                // drag the cursor along, otherwise scroll() would just put the view back
+                let last_row = (self.doc.row_offset + self.rows as usize - 1) as u32;
+                if self.doc.cy > last_row {
+                    self.move_to_line(last_row);
+                }
+            }
+            MouseKind::WheelDown => {
+                let max_offset = self.doc.text.line_count().saturating_sub(1);
+                self.doc.row_offset = (self.doc.row_offset + MEGA_WHEEL_LINES).min(max_offset);
+                if (self.doc.cy as usize) < self.doc.row_offset {
+                    self.move_to_line(self.doc.row_offset as u32);
+                }
+            }
+            // the status and message bars are below the text, clicks there dont do anything
+            MouseKind::Press if mouse.row >= self.top && row < self.rows => {
+                self.move_to_screen(row, col);
+                self.doc.anchor = Some((self.doc.cx, self.doc.cy));
+                self.doc.mark_set = false;
+            }
+            MouseKind::Drag if self.doc.anchor.is_some() => {
+                self.move_to_screen(row.min(self.rows - 1), col);
+            }
+            MouseKind::Release if self.doc.anchor == Some((self.doc.cx, self.doc.cy)) => {
+                self.clear_selection(); // just a click, nothing got selected
+            }
+            _ => {}
+        }
+    }
+    // the wheel when wrapping, which goes by rows on the screen rather than lines
+    fn scroll_rows(&mut self, up: bool) {
+        let (cursor, col) = self.cursor_row();
+        let top = (self.doc.row_offset, self.doc.wrap_offset);
+        let top = if up {
+            self.rows_up(top, MEGA_WHEEL_LINES)
+        } else {
+            self.rows_down(top, MEGA_WHEEL_LINES, false)
+        };
+        (self.doc.row_offset, self.doc.wrap_offset) = top;
+        // drag the cursor along, otherwise scroll() would just put the view back
+        let bottom = self.rows_down(top, self.rows as usize - 1, true);
+        if cursor > bottom {
+            self.move_to_row(bottom, col);
+        } else if cursor < top {
+            self.move_to_row(top, col);
+        }
+    }
+    // puts the cursor on whatever is drawn at that spot on the screen, the start of the line for
+    // a click on its number
+    fn move_to_screen(&mut self, row: u16, col: u16) {
+        let col = col.saturating_sub(self.gutter_width());
+        if self.doc.settings.wrap != Wrap::Off {
+            let top = (self.doc.row_offset, self.doc.wrap_offset);
+            self.move_to_row(self.rows_down(top, row as usize, false), col as usize);
+            return;
+        }
+        let last_line = self.doc.text.line_count() - 1;
+        self.doc.cy = (self.doc.row_offset + row as usize).min(last_line) as u32;
+        self.doc.rx = (self.doc.col_offset + col as usize) as u32;
+        self.convert_rx_to_cx();
+    }
+    // the selected part of the text from start to end, if there is one
+    fn selection(&self) -> Option<(Cursor, Cursor)> {
+        let anchor = self.doc.anchor?;
+        let cursor = (self.doc.cx, self.doc.cy);
+        if anchor == cursor {
+            return None;
+        }
+        // cursors are (cx, cy), so flip them round to compare line first
+        if (anchor.1, anchor.0) < (cursor.1, cursor.0) {
+            Some((anchor, cursor))
+        } else {
+            Some((cursor, anchor))
+        }
+    }
+    fn clear_selection(&mut self) {
+        self.doc.anchor = None;
+        self.doc.mark_set = false;
+    }
+    fn selection_range(&self) -> Option<Range<usize>> {
+        let (start, end) = self.selection()?;
+        Some(self.cursor_offset(start)..self.cursor_offset(end))
+    }
+    fn cursor_offset(&self, (cx, cy): Cursor) -> usize {
+        self.doc.text.offset(cy as usize, cx as usize)
+    }
+    // false if there was nothing selected
+    fn delete_selection(&mut self) -> bool {
+        let (Some(range), Some((start, _))) = (self.selection_range(), self.selection()) else {
+            self.clear_selection();
+            return false;
+        };
+        self.clear_selection();
+        self.delete_text(range, EditKind::Other);
+        (self.doc.cx, self.doc.cy) = start;
+        true
+    }
+    fn copy(&mut self) {
+        let Some(range) = self.selection_range() else {
+            self.set_status_message("Nothing selected");
+            return;
+        };
+        let bytes = self.doc.text.slice(range);
+        self.set_status_message(&format!("{} bytes copied", bytes.len()));
+        self.kill_ring.push(bytes);
+        self.share_kill();
+    }
+    fn cut(&mut self, chain: Chain) {
+        let Some(range) = self.selection_range() else {
+            self.set_status_message("Nothing selected");
+            return;
+        };
+        let bytes = self.doc.text.slice(range);
+        self.delete_selection();
+        self.kill(bytes, chain);
+    }
+    // the whole cursor line, newline and all. cutting a few lines in a row pastes them back together
+    fn cut_line(&mut self, chain: Chain) {
+        if self.doc.cy as usize >= self.doc.text.line_count() {
+            return;
+        }
+        let mut range = self.doc.text.line_range(self.doc.cy as usize);
+        if range.end < self.doc.text.len() {
+            range.end += 1;
+        }
+        if range.is_empty() {
+            return;
+        }
+        let bytes = self.doc.text.slice(range.clone());
+        self.clear_selection();
+        self.delete_text(range, EditKind::Other);
+        self.doc.cx = 0;
+        self.kill(bytes, chain);
+    }
+    fn kill(&mut self, bytes: Vec<u8>, chain: Chain) {
+        if chain == Chain::Kill {
+            self.kill_ring.append(&bytes);
+        } else {
+            self.kill_ring.push(bytes);
+        }
+        self.chain = Chain::Kill;
+        self.share_kill();
+    }
+    // puts the newest kill on the system clipboard too
+    fn share_kill(&mut self) {
+        if let Some(kill) = self.kill_ring.newest() {
+            self.buffer.extend_from_slice(&command::set_clipboard(kill));
+        }
+    }
+    fn yank(&mut self) {
+        let Some(kill) = self.kill_ring.yank().map(<[u8]>::to_vec) else {
+            self.set_status_message("Nothing to paste");
+            return;
+        };
+        self.delete_selection();
+        self.insert_block(&kill);
+        // insert_block might have had to add a line first, so go back from where the cursor is
+        let end = self.cursor_offset((self.doc.cx, self.doc.cy));
+        self.chain = Chain::Yank(end - kill.len()..end);
+    }
+    // straight after a paste, swaps what was pasted for the kill before it
+    fn yank_older(&mut self, chain: Chain) {
+        let Chain::Yank(range) = chain else {
+            self.set_status_message("Can only cycle right after a paste");
+            return;
+        };
+        let Some(kill) = self.kill_ring.rotate().map(<[u8]>::to_vec) else {
+            return;
+        };
+
+        self.doc.history.seal();
+        self.delete_text(range.clone(), EditKind::Batch);
+        self.insert_text(range.start, &kill, EditKind::Batch);
+        self.doc.history.seal();
 
-        if let Some(curr_row) = curr_row {
-            self.cx = self.cx.min(curr_row.len() as u32) // curr_row.len() is actually one more bc
-        // 0 indexed so you can go one more than the length of the line
+        let end = range.start + kill.len();
+        let (cy, cx) = self.doc.text.position(end);
+        self.doc.cy = cy as u32;
+        self.doc.cx = cx as u32;
+        self.chain = Chain::Yank(range.start..end);
+
+        let (which, of) = self.kill_ring.position();
+        self.set_status_message(&format!("Pasted kill {} of {}", which, of));
+    }
+    // tab and shift-tab on a selection indent or dedent every line in it, shift-tab without a
+    // selection dedents the cursor line
+    fn indent(&mut self, dedent: bool) {
+        let cursor = (self.doc.cx, self.doc.cy);
+        let (start, end) = self.selection().unwrap_or((cursor, cursor));
+        let first = start.1 as usize;
+        // a selection that ends at the very start of a line doesnt really take in that line
+        let last = if end.1 > start.1 && end.0 == 0 {
+            end.1 - 1
+        } else {
+            end.1
+        };
+        let last = (last as usize).min(self.doc.text.line_count() - 1);
+
+        let indent = if self.doc.settings.expand_tabs {
+            " ".repeat(self.doc.settings.tab_width)
+        } else {
+            "\t".to_string()
+        };
+        let width = indent.len() as u32;
+        self.doc.history.seal();
+        for y in first..=last {
+            let line = self.doc.text.line_bytes(y);
+            let offset = self.doc.text.line_start(y);
+            if dedent {
+                let n = if line.first() == Some(&b'\t') {
+                    1
+                } else {
+                    line.iter()
+                        .take(self.doc.settings.tab_width)
+                        .take_while(|&&b| b == b' ')
+                        .count()
+                };
+                if n == 0 {
+                    continue;
+                }
+                self.delete_text(offset..offset + n, EditKind::Batch);
+                self.shift_columns(y, |cx| cx.saturating_sub(n as u32));
+            } else if !line.is_empty() {
+                self.insert_text(offset, indent.as_bytes(), EditKind::Batch);
+                // something at the very start of the line stays there, so whole lines stay selected
+                self.shift_columns(y, |cx| if cx > 0 { cx + width } else { 0 });
+            }
+        }
+        self.doc.history.seal();
+    }
+    // keeps the cursor and the selection on the same text after line y changed in front of them
+    fn shift_columns(&mut self, y: usize, shift: impl Fn(u32) -> u32) {
+        if self.doc.cy as usize == y {
+            self.doc.cx = shift(self.doc.cx);
+        }
+        if let Some((cx, cy)) = &mut self.doc.anchor
+            && *cy as usize == y
+        {
+            *cx = shift(*cx);
+        }
+    }
+    // going up and down keeps the cursor in the same screen column, or as close as the new line
+    // gets to it. cx is a byte offset, so just keeping that could land in the middle of a char
+    fn move_to_line(&mut self, cy: u32) {
+        self.convert_cx_to_rx();
+        self.doc.cy = cy;
+        if (self.doc.cy as usize) < self.doc.text.line_count() {
+            self.convert_rx_to_cx();
         } else {
-            self.cx = 0;
+            self.doc.cx = 0;
         }
     }
 
+    // spaces up to the next tab stop instead of the tab with expand_tabs
+    fn insert_tab(&mut self) {
+        if !self.doc.settings.expand_tabs {
+            self.insert_char('\t');
+            return;
+        }
+        self.convert_cx_to_rx();
+        let tab_width = self.doc.settings.tab_width;
+        for _ in 0..tab_width - self.doc.rx as usize % tab_width {
+            self.insert_char(' ');
+        }
+    }
     fn insert_char(&mut self, c: char) {
-        if (self.cy as usize) >= self.lines.len() {
+        if (self.doc.cy as usize) >= self.doc.text.line_count() {
             self.insert_row();
         }
-        self.render[self.cy as usize].insert(self.rx as usize, c); // TODO: handle insert tabs
-        self.lines[self.cy as usize].insert(self.cx as usize, c);
-
-        self.cx += 1;
+        let mut bytes = [0u8; 4];
+        let bytes = c.encode_utf8(&mut bytes).as_bytes();
+        let offset = self
+            .doc
+            .text
+            .offset(self.doc.cy as usize, self.doc.cx as usize);
+        self.insert_text(offset, bytes, EditKind::Typing);
+
+        self.doc.cx += bytes.len() as u32;
+    }
+    // the whole paste goes in as one edit that is undone in one go, instead of key by key
+    fn paste(&mut self, bytes: &[u8]) {
+        // terminals send the newlines in a paste as '\r', same as the enter key
+        let mut text = Vec::with_capacity(bytes.len());
+        let mut bytes = bytes.iter().peekable();
+        while let Some(&b) = bytes.next() {
+            if b == b'\r' {
+                bytes.next_if_eq(&&b'\n');
+                text.push(b'\n');
+            } else {
+                text.push(b);
+            }
+        }
+        self.insert_block(&text);
+    }
+    // several lines at once as one undo step, used for pastes
+    fn insert_block(&mut self, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
 
-        self.dirty = true;
+        self.doc.history.seal();
+        if (self.doc.cy as usize) >= self.doc.text.line_count() {
+            self.insert_text(self.doc.text.len(), b"\n", EditKind::Batch);
+        }
+        let offset = self
+            .doc
+            .text
+            .offset(self.doc.cy as usize, self.doc.cx as usize);
+        self.insert_text(offset, text, EditKind::Batch);
+        self.doc.history.seal();
+
+        let (cy, cx) = self.doc.text.position(offset + text.len());
+        self.doc.cy = cy as u32;
+        self.doc.cx = cx as u32;
     }
     fn insert_row(&mut self) {
-        self.render.insert(self.cy as usize, String::new());
-        self.lines.insert(self.cy as usize, String::new());
-
-        self.dirty = true;
+        if (self.doc.cy as usize) < self.doc.text.line_count() {
+            let offset = self.doc.text.line_start(self.doc.cy as usize);
+            self.insert_text(offset, b"\n", EditKind::Typing);
+        } else {
+            // on the extra line at the bottom, so the new row goes after the last line
+            self.insert_text(self.doc.text.len(), b"\n", EditKind::Typing);
+        }
     }
     fn insert_newline(&mut self) {
-        if self.cx == 0 {
+        if self.doc.cx == 0 {
             self.insert_row();
         } else {
             //enter was pressed in the middle of the line
-            let second_half = self.lines[self.cy as usize].split_off(self.cx as usize);
-            self.lines.insert(self.cy as usize + 1, second_half);
-
-            let second_half = self.render[self.cy as usize].split_off(self.cx as usize);
-            self.render.insert(self.cy as usize + 1, second_half);
+            let offset = self
+                .doc
+                .text
+                .offset(self.doc.cy as usize, self.doc.cx as usize);
+            self.insert_text(offset, b"\n", EditKind::Typing);
         }
 
-        self.cy += 1;
-        self.cx = 0;
+        self.doc.cy += 1;
+        self.doc.cx = 0;
     }
     fn backspace_char(&mut self) {
-        if (self.cy as usize) < self.lines.len() && !(self.cx == 0 && self.cy == 0) {
+        if self.delete_selection() {
+            return;
+        }
+        if (self.doc.cy as usize) < self.doc.text.line_count()
+            && !(self.doc.cx == 0 && self.doc.cy == 0)
+        {
             // not on extra line at bottom and not on upper right corner
-
-            if self.cx > 0 {
-                //not at first char
-                self.render[self.cy as usize].remove(self.rx as usize - 1); // TODO: handle backspace tabs
-                self.lines[self.cy as usize].remove(self.cx as usize - 1);
-
-                self.cx -= 1;
+            let offset = self
+                .doc
+                .text
+                .offset(self.doc.cy as usize, self.doc.cx as usize);
+
+            if self.doc.cx > 0 {
+                //not at first char, take out the whole grapheme before the cursor
+                let line = self.doc.text.line_bytes(self.doc.cy as usize);
+                let start = unicode::prev_boundary(&line, self.doc.cx as usize);
+                self.delete_text(
+                    offset - (self.doc.cx as usize - start)..offset,
+                    EditKind::Backspace,
+                );
+                self.doc.cx = start as u32;
             } else {
-                let line = self.render.remove(self.cy as usize);
-                self.render[self.cy as usize - 1].push_str(&line);
-
-                let line = self.lines.remove(self.cy as usize);
-                self.cx = self.lines[self.cy as usize - 1].len() as u32;
-                self.lines[self.cy as usize - 1].push_str(&line);
+                // join with the line above by deleting the newline at the end of it
+                self.delete_text(offset - 1..offset, EditKind::Backspace);
+                self.doc.cy -= 1;
+                self.doc.cx = (offset - 1 - self.doc.text.line_start(self.doc.cy as usize)) as u32;
+            }
+        }
+    }
+    fn delete_char(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        if (self.doc.cy as usize) < self.doc.text.line_count() {
+            // at the end of a line this deletes the newline, pulling the next line up
+            let line = self.doc.text.line_bytes(self.doc.cy as usize);
+            let len = if (self.doc.cx as usize) < line.len() {
+                unicode::next_boundary(&line, self.doc.cx as usize) - self.doc.cx as usize
+            } else {
+                1
+            };
+            let offset = self
+                .doc
+                .text
+                .offset(self.doc.cy as usize, self.doc.cx as usize);
+            self.delete_text(offset..offset + len, EditKind::Delete);
+        }
+    }
+    // every change to the text goes through these two so it can be undone. call them before
+    // moving the cursor, the history remembers where it was
+    fn insert_text(&mut self, offset: usize, bytes: &[u8], kind: EditKind) {
+        self.doc
+            .highlighter
+            .invalidate(self.doc.text.position(offset).0);
+        self.doc.text.insert(offset, bytes);
+        self.doc.history.record(
+            Edit::Insert {
+                offset,
+                bytes: bytes.to_vec(),
+            },
+            kind,
+            (self.doc.cx, self.doc.cy),
+        );
+        self.doc.dirty = true;
+    }
+    fn delete_text(&mut self, range: Range<usize>, kind: EditKind) {
+        let offset = range.start;
+        self.doc
+            .highlighter
+            .invalidate(self.doc.text.position(offset).0);
+        let bytes = self.doc.text.delete(range);
+        if bytes.is_empty() {
+            return;
+        }
+        self.doc.history.record(
+            Edit::Delete { offset, bytes },
+            kind,
+            (self.doc.cx, self.doc.cy),
+        );
+        self.doc.dirty = true;
+    }
+    fn convert_line_endings(&mut self) {
+        let mut answer = String::new();
+        let Some(answer) = self.prompt(
+            "Convert line endings to (lf/crlf):",
+            &mut answer,
+            false,
+            |_, _, _| {},
+        ) else {
+            return;
+        };
+        let line_ending = match answer.trim().to_lowercase().as_str() {
+            "lf" | "unix" => LineEnding::Lf,
+            "crlf" | "dos" => LineEnding::Crlf,
+            _ => {
+                self.set_status_message(&format!("Unknown line ending <{}>", answer));
+                return;
+            }
+        };
+        if line_ending == self.doc.line_ending {
+            self.set_status_message(&format!("Line endings are already {}", line_ending.name()));
+            return;
+        }
 
-                self.cy -= 1;
+        // only what is recorded from here on belongs to the conversion
+        self.doc.history.seal();
+        if self.doc.line_ending == LineEnding::Mixed {
+            // the '\r's of the crlf lines are still in the text, take them out in the same undo
+            // step as the conversion
+            let old = self.doc.text.bytes();
+            let mut new = Vec::with_capacity(old.len());
+            let last = self.doc.text.line_count() - 1;
+            for (i, line) in old.split(|&b| b == b'\n').enumerate() {
+                if i > 0 {
+                    new.push(b'\n');
+                }
+                // a '\r' on the last line is only a line ending if a newline came after it
+                if i < last || self.doc.final_newline {
+                    new.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+                } else {
+                    new.extend_from_slice(line);
+                }
             }
+            self.delete_text(0..old.len(), EditKind::Batch);
+            self.insert_text(0, &new, EditKind::Batch);
+
+            if (self.doc.cy as usize) < self.doc.text.line_count() {
+                self.doc.cx = self
+                    .doc
+                    .cx
+                    .min(self.doc.text.line_len(self.doc.cy as usize) as u32);
+            }
+        }
 
-            self.dirty = true;
+        // undo has to put the old line endings back along with any carriage returns
+        let before = (self.doc.line_ending, self.doc.final_newline);
+        let after = (line_ending, self.doc.final_newline);
+        let cursor = (self.doc.cx, self.doc.cy);
+        self.doc.history.record_format(before, after, cursor);
+        self.doc.history.seal();
+        self.doc.line_ending = line_ending;
+        self.doc.dirty = true;
+        self.set_status_message(&format!("Line endings converted to {}", line_ending.name()));
+    }
+    fn undo(&mut self) {
+        let mut format = (self.doc.line_ending, self.doc.final_newline);
+        match self.doc.history.undo(&mut self.doc.text, &mut format) {
+            Some((cx, cy)) => {
+                (self.doc.line_ending, self.doc.final_newline) = format;
+                self.doc.highlighter.invalidate(0);
+                self.doc.cx = cx;
+                self.doc.cy = cy;
+                self.doc.dirty = !self.doc.history.is_saved();
+            }
+            None => self.set_status_message("Nothing to undo"),
+        }
+    }
+    fn redo(&mut self) {
+        let mut format = (self.doc.line_ending, self.doc.final_newline);
+        match self.doc.history.redo(&mut self.doc.text, &mut format) {
+            Some((cx, cy)) => {
+                (self.doc.line_ending, self.doc.final_newline) = format;
+                self.doc.highlighter.invalidate(0);
+                self.doc.cx = cx;
+                self.doc.cy = cy;
+                self.doc.dirty = !self.doc.history.is_saved();
+            }
+            None => self.set_status_message("Nothing to redo"),
         }
     }
 
     fn clear_screen(&self) {
-        stdout().write(command::CLEAR_SCREEN).unwrap();
-        stdout().write(command::MOVE_CURSOR_TOP_LEFT).unwrap();
+        stdout().write_all(command::CLEAR_SCREEN).unwrap();
+        stdout().write_all(command::MOVE_CURSOR_TOP_LEFT).unwrap();
         stdout().flush().unwrap();
     }
     fn convert_cx_to_rx(&mut self) {
-        self.rx = 0;
-        if (self.cy as usize) < self.lines.len() {
-            let curr_row = &self.lines[self.cy as usize];
+        self.doc.rx = 0;
+        if (self.doc.cy as usize) < self.doc.text.line_count() {
+            let curr_row = self.doc.text.line_bytes(self.doc.cy as usize);
 
-            for (i, c) in curr_row.chars().enumerate() {
-                if i == self.cx as usize {
+            for (i, unit) in unicode::units(&curr_row) {
+                if i >= self.doc.cx as usize {
                     break;
                 }
-
-                self.rx += 1; //count char
-                if c == '\t' {
-                    let spaces_needed = (MEGA_TAB_STOP - (i % MEGA_TAB_STOP)) as u32;
-                    self.rx += spaces_needed - 1; //already counted one for '\t'
-                }
+                let tab_width = self.doc.settings.tab_width;
+                self.doc.rx += render_unit(unit, self.doc.rx as usize, tab_width).1 as u32;
             }
         }
     }
     fn convert_rx_to_cx(&mut self) {
+        if (self.doc.cy as usize) < self.doc.text.line_count() {
+            self.doc.cx = self.rx_to_cx(self.doc.cy as usize, self.doc.rx as usize) as u32;
+        }
+    }
+    // the unit of line y drawn at column rx, or the one it is in the middle of for tabs and wide
+    // chars
+    fn rx_to_cx(&self, y: usize, rx: usize) -> usize {
+        let line = self.doc.text.line_bytes(y);
         let mut cur_rx = 0;
-        if (self.cy as usize) < self.render.len() {
-            let curr_row = &self.lines[self.cy as usize];
-            for (cx, c) in curr_row.chars().enumerate() {
-                if cur_rx == self.rx {
-                    self.cx = cx as u32;
-                    break;
-                }
-                cur_rx += 1;
-                if c == '\t' {
-                    let spaces_needed = (MEGA_TAB_STOP - (cx % MEGA_TAB_STOP)) as u32;
-                    self.rx += spaces_needed - 1; //already counted one for '\t'
-                }
+        for (cx, unit) in unicode::units(&line) {
+            cur_rx += render_unit(unit, cur_rx, self.doc.settings.tab_width).1;
+            if cur_rx > rx {
+                return cx;
             }
         }
+        line.len()
     }
     fn save(&mut self) -> io::Result<usize> {
-        if self.filename.is_none() {
+        if self.doc.filename.is_none() {
             let mut answer = String::new();
             loop {
-                match self.prompt("Enter a filename (ESC to cancel):", &mut answer, |_, _| {}) {
+                match self.prompt(
+                    "Enter a filename (ESC to cancel):",
+                    &mut answer,
+                    false,
+                    |_, _, _| {},
+                ) {
                     Some(path) => {
                         let path = PathBuf::from(&path);
                         if Path::exists(&path) {
@@ -496,7 +1705,11 @@ impl Editor {
                             "WARNING!!! Filename exists and will be overwritten. Press ! to OVERWRITE; any key to cancel",
                         );
                             self.refresh_screen();
-                            let Some(Key::Char('!')) = self.read_key() else {
+                            let Some(Key {
+                                code: KeyCode::Char('!'),
+                                ..
+                            }) = self.read_key()
+                            else {
                                 continue;
                             };
                         }
@@ -507,185 +1720,379 @@ impl Editor {
                     }
                 }
             }
-            self.filename = Some(answer.into());
+            self.doc.set_filename(answer.into());
+            self.doc.settings = self.config.settings_for(self.doc.highlighter.syntax());
         }
         // at this point will have filename
-        let filename = self.filename.as_ref().unwrap();
-        let mut file = File::create(filename)?;
-        let buf = self.lines.join("\n");
-        file.write_all(buf.as_bytes())?;
-        self.dirty = false;
-        return Ok(buf.as_bytes().len());
+        let filename = self.doc.filename.as_ref().unwrap();
+        let buf = self.doc.encode();
+        file::write(filename, &buf)?;
+        self.doc.mark_saved();
+        Ok(buf.len())
+    }
+    // for when the editor is going down hard, every buffer with changes gets its own file
+    pub fn write_recovery(&self) {
+        for buffer in self.all_buffers() {
+            buffer.write_recovery();
+        }
     }
     fn prompt<'a>(
         &mut self,
         prompt: &str,
         answer: &'a mut String,
-        mut callback: impl FnMut(&mut Self, &String),
+        allow_empty: bool,
+        mut callback: impl FnMut(&mut Self, &String, Key),
     ) -> Option<&'a mut String> {
-        loop {
-            self.set_status_message(&format!("{} {}", prompt, &answer));
+        let answer = loop {
+            self.set_status_message(&format!("{} {}{}", prompt, &answer, self.prompt_note));
             self.refresh_screen();
 
-            if let Some(key) = self.read_key() {
-                match key {
-                    Key::Char(keys::BACKSPACE) => {
-                        let _ = answer.pop();
-
-                        callback(self, answer);
+            let key = match self.read_event() {
+                Some(Event::Key(key)) => key,
+                Some(Event::Paste(bytes)) => {
+                    // as if it was typed, up to the end of the first line
+                    let pasted = prompt_paste(&bytes);
+                    if let Some(last) = pasted.chars().last() {
+                        answer.push_str(&pasted);
+                        callback(self, answer, Key::new(KeyCode::Char(last)));
                     }
-                    Key::Char(c) => {
-                        if c == keys::ENTER && answer.len() > 0 {
-                            self.set_status_message("");
-                            return Some(answer);
-                        }
-                        if !c.is_control() {
-                            answer.push(c);
+                    continue;
+                }
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Backspace => {
+                    let _ = answer.pop();
 
-                            callback(self, answer);
-                        }
-                    }
-                    Key::Special(EscapeSeq::Escape) => {
-                        self.set_status_message("");
-                        break;
-                    }
-                    _ => {}
+                    callback(self, answer, key);
+                }
+                KeyCode::Enter if allow_empty || !answer.is_empty() => {
+                    self.set_status_message("");
+                    break Some(answer);
                 }
+                KeyCode::Char(c) if key.mods.is_empty() && !c.is_control() => {
+                    answer.push(c);
+
+                    callback(self, answer, key);
+                }
+                KeyCode::Escape => {
+                    self.set_status_message("");
+                    break None;
+                }
+                // anything else is up to the callback, for toggles and the like
+                _ => callback(self, answer, key),
             }
-        }
-        None
+        };
+        self.prompt_note.clear();
+        answer
     }
-    fn find(&mut self) {
-        let cx_save = self.cx;
-        let cy_save = self.cy;
-        let row_offset_save = self.row_offset;
-        let col_offset_save = self.col_offset;
-
-        let callback = |editor: &mut Self, answer: &String| {
-            for (iy, line) in editor.render.iter().enumerate() {
-                if let Some(ix) = line.find(answer.as_str()) {
-                    editor.cy = iy as u32;
-                    editor.rx = ix as u32;
-                    editor.convert_rx_to_cx();
-                    editor.row_offset = editor.render.len(); //huh?
-                    break;
-                }
+    // steps through the matches from the cursor on, asking about each one unless told to do all,
+    // and carries on from the top until it gets back round to where it started
+    fn replace(&mut self) {
+        let mut search = String::new();
+        let callback = |editor: &mut Self, answer: &String, key: Key| {
+            editor.toggle_search_option(key);
+            let note = editor.search_note();
+            editor.prompt_note = match editor.search_pattern(answer) {
+                Err(error) => format!("{} ({})", note, error),
+                Ok(_) => note,
+            };
+        };
+        self.prompt_note = self.search_note();
+        if self
+            .prompt(
+                "Replace: (ESC to cancel, Alt-R/C/W regex/case/word)",
+                &mut search,
+                false,
+                callback,
+            )
+            .is_none()
+        {
+            return;
+        }
+        let pattern = match self.search_pattern(&search) {
+            Ok(pattern) => pattern,
+            Err(error) => {
+                self.set_status_message(&format!("Bad search: {}", error));
+                return;
             }
         };
-        let mut answer = String::new();
-        match self.prompt(
-            "Search: (ESC to cancel, ENTER to confirm)",
-            &mut answer,
-            callback,
-        ) {
-            Some(_) => {
-                //next and prev matches
-                let mut rx_match = self.rx;
-                let mut cy_match = self.cy;
+        let mut replacement = String::new();
+        if self
+            .prompt(
+                &format!("Replace <{}> with:", search),
+                &mut replacement,
+                true,
+                |_, _, _| {},
+            )
+            .is_none()
+        {
+            return;
+        }
 
-                self.set_status_message(&format!(
-                    "Searching for <{}> - next -> - ESC to cancel",
-                    &answer
-                ));
+        let origin = if (self.doc.cy as usize) < self.doc.text.line_count() {
+            (self.doc.cy as usize, self.doc.cx as usize)
+        } else {
+            (0, 0)
+        };
+        let mut replace = Replace::new(origin);
+        let mut cursor = origin;
+        let mut count = 0;
+        let mut all = false;
+        let mut error = None;
+        // the whole lot comes back with one undo
+        self.doc.history.seal();
+        'matches: loop {
+            let (y, m) = match replace.next(&self.doc.text, |line, from| pattern.find(line, from)) {
+                Ok(Some(found)) => found,
+                Ok(None) => break,
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            };
+            if !all {
+                // show the match as a selection
+                self.doc.anchor = Some((m.start as u32, y as u32));
+                (self.doc.cx, self.doc.cy) = (m.end as u32, y as u32);
+                self.set_status_message("Replace this one? (y)es (n)o (a)ll (q)uit");
                 self.refresh_screen();
 
                 loop {
-                    match self.read_key() {
-                        Some(Key::Special(EscapeSeq::RightArrow)) => {
-                            for iy in cy_match as usize..self.render.len() {
-                                let line = &self.render[iy];
-                                if line.len() == 0 {
-                                    continue;
-                                }
-
-                                let start = if iy != cy_match as usize {
-                                    0
-                                } else {
-                                    rx_match as usize + 1
-                                };
-
-                                if let Some(ix) = line[start..].find(answer.as_str()) {
-                                    self.cy = iy as u32;
-                                    self.rx = start as u32 + (ix as u32);
-                                    self.convert_rx_to_cx();
-                                    rx_match = self.rx;
-                                    cy_match = self.cy;
-                                    self.row_offset = self.render.len();
-
-                                    self.set_status_message(&format!(
-                                        "Searching for <{}> - <-  prev  next -> - ESC to cancel",
-                                        &answer
-                                    ));
-                                    break;
-                                } else {
-                                    //no more matches to the right -- this is actually buggy
-                                    //because we only know there are no more after already pressed
-                                    //the right arrow to find out, but whatever
-                                    self.set_status_message(&format!(
-                                        "Searching for <{}> - <- prev - ESC to cancel",
-                                        &answer
-                                    ));
-                                }
-                            }
+                    match self.read_key().map(|key| key.code) {
+                        Some(KeyCode::Char('y')) => break,
+                        Some(KeyCode::Char('n')) => {
+                            cursor = (y, m.end);
+                            replace.skip(&self.doc.text, y, &m);
+                            continue 'matches;
                         }
-                        Some(Key::Special(EscapeSeq::LeftArrow)) => {
-                            for iy in (0..=cy_match as usize).rev() {
-                                let line = &self.render[iy];
-
-                                if line.len() == 0 {
-                                    continue;
-                                }
-
-                                let end = if iy != cy_match as usize {
-                                    line.len()
-                                } else {
-                                    rx_match as usize + 1
-                                };
-
-                                if let Some(ix) = line[..end].rfind(answer.as_str()) {
-                                    self.cy = iy as u32;
-                                    self.rx = ix as u32;
-                                    self.convert_rx_to_cx();
-
-                                    rx_match = self.rx;
-                                    cy_match = self.cy;
-
-                                    self.row_offset = self.render.len();
-                                    self.set_status_message(&format!(
-                                        "Searching for <{}> - <-  prev  next -> - ESC to cancel",
-                                        &answer
-                                    ));
-                                    break;
-                                } else {
-                                    //no more matches to the left -- this is actually buggy
-                                    //because we only know there are no more after already pressed
-                                    //the right arrow to find out, but whatever
-                                    self.set_status_message(&format!(
-                                        "Searching for <{}> - next -> - ESC to cancel",
-                                        &answer
-                                    ));
-                                }
-                            }
-                        }
-                        Some(Key::Special(EscapeSeq::Escape)) => {
-                            self.set_status_message("");
+                        Some(KeyCode::Char('a')) => {
+                            all = true;
                             break;
                         }
+                        Some(KeyCode::Char('q') | KeyCode::Escape) | None => break 'matches,
+                        _ => {}
+                    }
+                }
+            }
+
+            let start = self.doc.text.line_start(y) + m.start;
+            self.delete_text(start..start + m.len(), EditKind::Batch);
+            if !replacement.is_empty() {
+                self.insert_text(start, replacement.as_bytes(), EditKind::Batch);
+            }
+            replace.replaced(&self.doc.text, y, &m, replacement.len());
+            cursor = (y, m.start + replacement.len());
+            count += 1;
+        }
+        self.doc.history.seal();
+
+        self.clear_selection();
+        (self.doc.cx, self.doc.cy) = (cursor.1 as u32, cursor.0 as u32);
+        let stopped = error.map_or(String::new(), |error| format!(", stopped: {}", error));
+        self.set_status_message(&format!(
+            "{} replacement{} made{}",
+            count,
+            if count == 1 { "" } else { "s" },
+            stopped
+        ));
+    }
+    // searches as you type from the cursor on, then the arrows step through the matches. enter
+    // stays on the match, escape goes back to where the search started
+    fn find(&mut self) {
+        let cx_save = self.doc.cx;
+        let cy_save = self.doc.cy;
+        let row_offset_save = self.doc.row_offset;
+        let wrap_offset_save = self.doc.wrap_offset;
+        let col_offset_save = self.doc.col_offset;
+
+        let origin = if (self.doc.cy as usize) < self.doc.text.line_count() {
+            (self.doc.cy as usize, self.doc.cx as usize)
+        } else {
+            (0, 0)
+        };
 
-                        Some(_) => {}
-                        None => {}
+        let callback = |editor: &mut Self, answer: &String, key: Key| {
+            editor.toggle_search_option(key);
+            editor.search = None;
+            (editor.doc.cx, editor.doc.cy) = (cx_save, cy_save);
+            (editor.doc.row_offset, editor.doc.col_offset) = (row_offset_save, col_offset_save);
+            editor.doc.wrap_offset = wrap_offset_save;
+
+            let note = editor.search_note();
+            editor.prompt_note = match editor.search_pattern(answer) {
+                Err(error) => format!("{} ({})", note, error),
+                Ok(_) if answer.is_empty() => note,
+                Ok(pattern) => match editor.next_match(&pattern, origin, true) {
+                    Ok(Some((current, wrapped))) => {
+                        editor.jump_to_match(&current);
+                        editor.search = Some(Search {
+                            pattern,
+                            current,
+                            starts: None,
+                        });
+                        format!("{} {}", note, editor.match_position(wrapped, true))
+                    }
+                    Ok(None) => format!("{} no matches", note),
+                    Err(error) => format!("{} ({})", note, error),
+                },
+            };
+        };
+        self.prompt_note = self.search_note();
+        let mut answer = String::new();
+        let confirmed = self
+            .prompt(
+                "Search: (ESC/ENTER, Alt-R/C/W regex/case/word)",
+                &mut answer,
+                false,
+                callback,
+            )
+            .is_some();
+
+        if confirmed && self.search.is_some() {
+            let mut position = self.match_position(false, true);
+            loop {
+                self.set_status_message(&format!(
+                    "Searching for <{}> - {} - <- prev  next -> - ENTER to stay, ESC to cancel",
+                    &answer, position
+                ));
+                self.refresh_screen();
+
+                let forward = match self.read_key().map(|key| key.code) {
+                    Some(KeyCode::Right | KeyCode::Down) => true,
+                    Some(KeyCode::Left | KeyCode::Up) => false,
+                    Some(KeyCode::Enter) => {
+                        self.search = None;
+                        self.set_status_message("");
+                        return;
+                    }
+                    Some(KeyCode::Escape) => break,
+                    _ => continue,
+                };
+                let Some(mut search) = self.search.take() else {
+                    break;
+                };
+                let (y, current) = &search.current;
+                let from = if forward {
+                    (*y, search::past(&self.doc.text.line_bytes(*y), current))
+                } else {
+                    (*y, current.start)
+                };
+                match self.next_match(&search.pattern, from, forward) {
+                    Ok(Some((next, wrapped))) => {
+                        search.current = next;
+                        self.jump_to_match(&search.current);
+                        self.search = Some(search);
+                        position = self.match_position(wrapped, forward);
+                    }
+                    Ok(None) => self.search = Some(search),
+                    Err(error) => {
+                        self.search = Some(search);
+                        position = error.to_string();
                     }
-                    self.refresh_screen();
                 }
             }
-            None => {}
         }
 
         //esc pressed, so put cursor back
-        self.cx = cx_save;
-        self.cy = cy_save;
-        self.row_offset = row_offset_save;
-        self.col_offset = col_offset_save;
+        self.search = None;
+        self.set_status_message("");
+        self.doc.cx = cx_save;
+        self.doc.cy = cy_save;
+        self.doc.row_offset = row_offset_save;
+        self.doc.wrap_offset = wrap_offset_save;
+        self.doc.col_offset = col_offset_save;
+    }
+    // the first match starting at or after `from` going forwards, or the last one starting before
+    // it going backwards, carrying on round the other end of the file. true if it had to
+    fn next_match(
+        &self,
+        pattern: &Pattern,
+        from: (usize, usize),
+        forward: bool,
+    ) -> Result<Option<(Match, bool)>, regex::Error> {
+        let lines = self.doc.text.line_count();
+        for k in 0..=lines {
+            let (y, wrapped) = if forward {
+                ((from.0 + k) % lines, from.0 + k >= lines)
+            } else {
+                ((from.0 + lines - k) % lines, k > from.0)
+            };
+            let line = self.doc.text.line_bytes(y);
+            // the last time round is the start line again, for the part before from
+            let found = if forward {
+                let start = if k == 0 { from.1 } else { 0 };
+                pattern
+                    .find(&line, start)?
+                    .filter(|m| k < lines || m.start < from.1)
+            } else {
+                let end = if k == 0 { from.1 } else { line.len() + 1 };
+                pattern
+                    .rfind(&line, end)?
+                    .filter(|m| k < lines || m.start >= from.1)
+            };
+            if let Some(m) = found {
+                return Ok(Some(((y, m), wrapped)));
+            }
+        }
+        Ok(None)
+    }
+    fn jump_to_match(&mut self, (y, m): &Match) {
+        (self.doc.cx, self.doc.cy) = (m.start as u32, *y as u32);
+    }
+    // "3 of 17" for the match the search is on, and a notice if getting there went round the end.
+    // the matches are counted once per search, the text cant change while find is running
+    fn match_position(&mut self, wrapped: bool, forward: bool) -> String {
+        let Some(search) = &mut self.search else {
+            return String::new();
+        };
+        let starts = search
+            .starts
+            .get_or_insert_with(|| match_starts(&self.doc.text, &search.pattern));
+        let count = match_count(starts, (search.current.0, search.current.1.start));
+        let notice = match (wrapped, forward) {
+            (false, _) => "",
+            (true, true) => " (wrapped to the top)",
+            (true, false) => " (wrapped to the bottom)",
+        };
+        format!("{}{}", count, notice)
+    }
+    // the search matches on line y as bytes of the line, true for the one the cursor is on. empty
+    // regex matches are left out since there is nothing to draw
+    fn search_matches(&self, y: usize) -> Vec<(Range<usize>, bool)> {
+        let Some(search) = &self.search else {
+            return Vec::new();
+        };
+        search
+            .pattern
+            .find_all(&self.doc.text.line_bytes(y))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|m| !m.is_empty())
+            .map(|m| {
+                let current = search.current.0 == y && search.current.1 == m;
+                (m, current)
+            })
+            .collect()
+    }
+    // alt-r, alt-c and alt-w in the find and replace prompts
+    fn toggle_search_option(&mut self, key: Key) {
+        if key.mods != Modifiers::ALT {
+            return;
+        }
+        let options = &mut self.search_options;
+        match key.code {
+            KeyCode::Char('r') => options.regex = !options.regex,
+            KeyCode::Char('c') => options.case = options.case.next(),
+            KeyCode::Char('w') => options.whole_word = !options.whole_word,
+            _ => {}
+        }
+    }
+    fn search_note(&self) -> String {
+        self.search_options.describe()
+    }
+    fn search_pattern(&self, answer: &str) -> Result<Pattern, regex::Error> {
+        Pattern::new(answer, self.search_options)
     }
     fn set_status_message(&mut self, msg: &str) {
         self.status_msg.clear();
@@ -694,36 +2101,484 @@ impl Editor {
     }
 }
 
+// the bytes of line y that fall between start and end
+fn selected_bytes(y: usize, start: Cursor, end: Cursor) -> Range<usize> {
+    let (y, start_y, end_y) = (y as u32, start.1, end.1);
+    if y < start_y || y > end_y {
+        return 0..0;
+    }
+    let from = if y == start_y { start.0 as usize } else { 0 };
+    let to = if y == end_y {
+        end.0 as usize
+    } else {
+        usize::MAX
+    };
+    from..to
+}
+
+// the part of the screen the windows share, which is all of it but the message bar
+fn screen_area() -> Rect {
+    let (cols, rows) = terminal::size().expect("couldnt get size of terminal window");
+    Rect {
+        top: 0,
+        left: 0,
+        rows: rows.saturating_sub(1).max(2),
+        cols: cols.max(1),
+    }
+}
+
+// where each row of a wrapped line starts, as (byte, column) pairs. at words it breaks after the
+// last space that fits, unless a word is too long for a row all by itself
+fn wrap_points(line: &[u8], width: usize, settings: Settings) -> Vec<(usize, usize)> {
+    let words = settings.wrap == Wrap::Words;
+    let mut points = vec![(0, 0)];
+    let mut space = None; // the last place the row could break between words
+    let mut rx = 0;
+    for (i, unit) in unicode::units(line) {
+        let width_here = render_unit(unit, rx, settings.tab_width).1;
+        loop {
+            let row_start = points[points.len() - 1];
+            let fits = rx + width_here - row_start.1 <= width;
+            // a space can hang off the end into the spare column, rather than start a row
+            let hangs = words && matches!(unit, Unit::Grapheme(" ")) && rx - row_start.1 == width;
+            if fits || hangs || rx == row_start.1 {
+                break;
+            }
+            match space.take() {
+                Some(point) if words && point > row_start => points.push(point),
+                _ => points.push((i, rx)),
+            }
+        }
+        rx += width_here;
+        if matches!(unit, Unit::Grapheme(" " | "\t")) {
+            space = Some((i + 1, rx));
+        }
+    }
+    points
+}
+
+// the line and byte each match starts at, for counting them. it stops once there are more than
+// MEGA_MAX_MATCHES, so a common pattern in a big file doesnt hold up every key
+fn match_starts(text: &PieceTable, pattern: &Pattern) -> Vec<(usize, usize)> {
+    let mut starts = Vec::new();
+    for y in 0..text.line_count() {
+        // a line too long for the pattern doesnt count, next_match says so when it gets there
+        for m in pattern.find_all(&text.line_bytes(y)).unwrap_or_default() {
+            if starts.len() > MEGA_MAX_MATCHES {
+                return starts;
+            }
+            starts.push((y, m.start));
+        }
+    }
+    starts
+}
+
+// "3 of 17" for the match starting at current, or "3 of 1000+" when counting stopped early
+fn match_count(starts: &[(usize, usize)], current: (usize, usize)) -> String {
+    let which = starts.partition_point(|&start| start <= current);
+    if starts.len() <= MEGA_MAX_MATCHES {
+        return format!("{} of {}", which, starts.len());
+    }
+    if which > MEGA_MAX_MATCHES {
+        format!("{}+ of {}+", MEGA_MAX_MATCHES, MEGA_MAX_MATCHES)
+    } else {
+        format!("{} of {}+", which, MEGA_MAX_MATCHES)
+    }
+}
+
+// what a paste puts in a prompt, which only takes one line
+fn prompt_paste(bytes: &[u8]) -> String {
+    let line = bytes
+        .split(|&b| b == b'\n' || b == b'\r')
+        .next()
+        .unwrap_or_default();
+    String::from_utf8_lossy(line)
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect()
+}
+
+// which row of a wrapped line the byte at cx is on
+fn wrap_row(points: &[(usize, usize)], cx: usize) -> usize {
+    points
+        .iter()
+        .rposition(|&(start, _)| start <= cx)
+        .unwrap_or(0)
+}
+
+// what a unit looks like on screen when it starts at column rx, and how many columns that takes
+fn render_unit(unit: Unit<'_>, rx: usize, tab_width: usize) -> (Cow<'_, str>, usize) {
+    match unit {
+        Unit::Grapheme("\t") => {
+            let spaces_needed = tab_width - (rx % tab_width);
+            (" ".repeat(spaces_needed).into(), spaces_needed)
+        }
+        Unit::Grapheme(g) if g.starts_with(char::is_control) => {
+            // ^A and friends, sending the real thing would mess up the terminal
+            let c = g.chars().next().unwrap_or_default() as u32;
+            let caret = char::from_u32((c & 0x7f) ^ 0x40).unwrap_or('?');
+            (format!("^{caret}").into(), 2)
+        }
+        Unit::Grapheme(g) => (g.into(), unicode::grapheme_width(g)),
+        Unit::Invalid(b) => (format!("\\x{b:02X}").into(), 4),
+    }
+}
+
 impl Drop for Editor {
     fn drop(&mut self) {
         self.clear_screen();
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-enum Key {
-    Char(char),
-    Special(EscapeSeq),
+// what the last key did, for the keys that carry on from it
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Chain {
+    None,
+    Kill,
+    Yank(Range<usize>), // where the pasted text is
+}
+
+// the search find is doing, draw_rows highlights its matches while it lasts
+struct Search {
+    pattern: Pattern,
+    current: Match,                      // the one the cursor is on
+    starts: Option<Vec<(usize, usize)>>, // where the matches are, once they have been counted
+}
+
+// the line a match is on and its bytes in that line
+type Match = (usize, Range<usize>);
+
+// what the gutter shows, cycled through with alt-n
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative, // how far each line is from the cursor
+    Hybrid,   // relative, except for the cursor line which gets its real number
+}
+
+impl LineNumbers {
+    fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Hybrid,
+            LineNumbers::Hybrid => LineNumbers::Off,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Hybrid => "hybrid",
+        }
+    }
+}
+
+// how long lines are shown, cycled through with alt-z. wrapping only changes the drawing, the
+// lines in the file stay as long as they are
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Wrap {
+    Off, // scrolled sideways instead
+    Anywhere,
+    Words, // broken after a space where there is one, so words stay in one piece
+}
+
+impl Wrap {
+    fn next(self) -> Self {
+        match self {
+            Wrap::Off => Wrap::Anywhere,
+            Wrap::Anywhere => Wrap::Words,
+            Wrap::Words => Wrap::Off,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Wrap::Off => "off",
+            Wrap::Anywhere => "on",
+            Wrap::Words => "at words",
+        }
+    }
+}
+
+// the columns of a line drawn on one row of a window
+struct Segment {
+    line: usize,
+    start: usize,
+    end: usize,
+    first: bool, // the row the line starts on, which gets its number
+}
+
+// how a char is drawn apart from its syntax color
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Style {
+    Plain,
+    Selected,
+    Match,
+    CurrentMatch,
 }
 
-impl Key {
-    fn control(self) -> Self {
+impl Style {
+    fn colors(self, theme: &Theme) -> Vec<u8> {
         match self {
-            Key::Char(c) => Key::Char((c as u8 & 0x1f) as char),
-            Key::Special(_) => self,
+            Style::Plain => Vec::new(),
+            Style::Selected | Style::CurrentMatch => command::INVERTED_COLORS.to_vec(),
+            Style::Match => command::set_background(theme.matches),
         }
     }
 }
-#[derive(PartialEq, Eq, Debug)]
-enum EscapeSeq {
-    RightArrow,
-    LeftArrow,
-    UpArrow,
-    DownArrow,
-    PageUp,
-    PageDown,
-    Home,
-    End,
-    Delete,
-    Escape,
+
+// where a replace has got to. it goes from the cursor round the end of the file and back, and
+// since a match never spans lines and the replacement cant hold a newline, line numbers stay
+// put. only the column it started at can move, when a match before it on that line is replaced
+struct Replace {
+    origin: (usize, usize),
+    from: (usize, usize), // where to look for the next match
+    wrapped: bool,
+}
+
+impl Replace {
+    fn new(origin: (usize, usize)) -> Self {
+        Replace {
+            origin,
+            from: origin,
+            wrapped: false,
+        }
+    }
+
+    // the line and bytes of the next match, None once it is back where it started
+    fn next(
+        &mut self,
+        text: &PieceTable,
+        find: impl Fn(&[u8], usize) -> Result<Option<Range<usize>>, regex::Error>,
+    ) -> Result<Option<Match>, regex::Error> {
+        loop {
+            let (y, x) = self.from;
+            if let Some(m) = find(&text.line_bytes(y), x)? {
+                if self.wrapped && (y, m.start) >= self.origin {
+                    return Ok(None);
+                }
+                return Ok(Some((y, m)));
+            }
+            if self.wrapped && y >= self.origin.0 {
+                return Ok(None);
+            }
+            if y + 1 < text.line_count() {
+                self.from = (y + 1, 0);
+            } else {
+                self.wrapped = true;
+                self.from = (0, 0);
+            }
+        }
+    }
+
+    // leaves the match as it is and carries on after it
+    fn skip(&mut self, text: &PieceTable, y: usize, m: &Range<usize>) {
+        self.from = (y, search::past(&text.line_bytes(y), m));
+    }
+
+    // the match has been replaced by len bytes, carry on after them. an empty match would turn
+    // up again right after its replacement, so that steps over a char as well
+    fn replaced(&mut self, text: &PieceTable, y: usize, m: &Range<usize>, len: usize) {
+        if self.wrapped && y == self.origin.0 {
+            self.origin.1 = self.origin.1.max(m.end) + len - m.len();
+        }
+        let end = m.start + len;
+        self.from = if m.is_empty() {
+            (y, search::past(&text.line_bytes(y), &(end..end)))
+        } else {
+            (y, end)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // replaces the regex in text the way the editor does, starting from origin. each match
+    // takes the next answer, y or n, and everything is replaced once they run out
+    fn replace(
+        text: &str,
+        origin: (usize, usize),
+        regex: &str,
+        with: &str,
+        answers: &str,
+    ) -> (String, usize) {
+        let options = search::Options {
+            regex: true,
+            ..Default::default()
+        };
+        let pattern = Pattern::new(regex, options).unwrap();
+        let mut text = PieceTable::from_bytes(text.as_bytes().to_vec());
+        let mut replace = Replace::new(origin);
+        let mut answers = answers.chars();
+        let mut count = 0;
+        while let Some((y, m)) = replace
+            .next(&text, |line, from| pattern.find(line, from))
+            .unwrap()
+        {
+            if answers.next() == Some('n') {
+                replace.skip(&text, y, &m);
+                continue;
+            }
+            let start = text.line_start(y) + m.start;
+            text.delete(start..start + m.len());
+            text.insert(start, with.as_bytes());
+            replace.replaced(&text, y, &m, with.len());
+            count += 1;
+        }
+        (String::from_utf8(text.bytes()).unwrap(), count)
+    }
+
+    #[test]
+    fn goes_round_once() {
+        let text = "foo Foo\nbar foo\nfoo foo\n";
+        // starting inside a match, which only gets replaced at the end
+        assert_eq!(
+            replace(text, (1, 5), "foo", "X", ""),
+            ("X Foo\nbar X\nX X\n".to_string(), 4)
+        );
+        assert_eq!(
+            replace(text, (1, 5), "foo", "X", "yny"),
+            ("X Foo\nbar X\nX foo\n".to_string(), 3)
+        );
+        assert_eq!(
+            replace(text, (0, 0), "foo", "X", "nnnn"),
+            (text.to_string(), 0)
+        );
+        assert_eq!(replace("", (0, 0), "foo", "X", ""), (String::new(), 0));
+    }
+
+    #[test]
+    fn replacements_containing_the_match() {
+        assert_eq!(
+            replace("aaa", (0, 1), "a", "aa", ""),
+            ("aaaaaa".to_string(), 3)
+        );
+        assert_eq!(
+            replace("ab\nab ab", (1, 0), "ab", "abab", ""),
+            ("abab\nabab abab".to_string(), 3)
+        );
+        // the replacement before the start pushes it along, so the match after it isnt missed
+        assert_eq!(
+            replace("a a a", (0, 4), "a", "aaa", ""),
+            ("aaa aaa aaa".to_string(), 3)
+        );
+    }
+
+    #[test]
+    fn replacements_shrinking_the_line() {
+        assert_eq!(
+            replace("ab ab ab", (0, 4), "ab", "", ""),
+            ("  ".to_string(), 3)
+        );
+        assert_eq!(
+            replace("xab\nab", (0, 2), "ab", "b", ""),
+            ("xb\nb".to_string(), 2)
+        );
+    }
+    #[test]
+    fn empty_matches_step_over_a_char() {
+        assert_eq!(
+            replace("ab", (0, 0), "x*", "-", ""),
+            ("-a-b-".to_string(), 3)
+        );
+        assert_eq!(
+            replace("ab", (0, 1), "x*", "-", ""),
+            ("-a-b-".to_string(), 3)
+        );
+        assert_eq!(
+            replace("ab\ncd", (0, 0), "^", "> ", ""),
+            ("> ab\n> cd".to_string(), 2)
+        );
+        assert_eq!(
+            replace("ab\ncd", (1, 1), "$", ";", "n"),
+            ("ab;\ncd".to_string(), 1)
+        );
+    }
+    fn wrap(line: &[u8], width: usize, wrap: Wrap) -> Vec<(usize, usize)> {
+        let settings = Settings {
+            wrap,
+            ..Settings::default()
+        };
+        wrap_points(line, width, settings)
+    }
+
+    #[test]
+    fn wrapping_at_characters() {
+        assert_eq!(wrap(b"", 4, Wrap::Anywhere), [(0, 0)]);
+        assert_eq!(wrap(b"abcd", 4, Wrap::Anywhere), [(0, 0)]);
+        assert_eq!(
+            wrap(b"abcdefghij", 4, Wrap::Anywhere),
+            [(0, 0), (4, 4), (8, 8)]
+        );
+        assert_eq!(
+            wrap(b"aaa bbb ccc", 5, Wrap::Anywhere),
+            [(0, 0), (5, 5), (10, 10)]
+        );
+        // a wide char that doesnt fit goes to the next row whole
+        assert_eq!(
+            wrap("日本語".as_bytes(), 5, Wrap::Anywhere),
+            [(0, 0), (6, 4)]
+        );
+    }
+
+    #[test]
+    fn wrapping_at_words() {
+        assert_eq!(
+            wrap(b"aaa bbb ccc", 5, Wrap::Words),
+            [(0, 0), (4, 4), (8, 8)]
+        );
+        // too long for a row, so it gets broken anyway
+        assert_eq!(wrap(b"a bcdefgh", 4, Wrap::Words), [(0, 0), (2, 2), (6, 6)]);
+        // the space hangs off the end instead of starting the next row
+        assert_eq!(wrap(b"abcd efg", 4, Wrap::Words), [(0, 0), (5, 5)]);
+    }
+
+    #[test]
+    fn wrapping_tabs() {
+        let settings = Settings {
+            tab_width: 4,
+            wrap: Wrap::Anywhere,
+            ..Settings::default()
+        };
+        assert_eq!(wrap_points(b"a\tbcd", 6, settings), [(0, 0), (4, 6)]);
+        assert_eq!(wrap(b"a\tbcd", 6, Wrap::Anywhere), [(0, 0), (1, 1), (2, 8)]);
+    }
+
+    #[test]
+    fn rows_of_a_wrapped_line() {
+        let points = [(0, 0), (4, 4), (8, 8)];
+        assert_eq!(wrap_row(&points, 0), 0);
+        assert_eq!(wrap_row(&points, 3), 0);
+        assert_eq!(wrap_row(&points, 4), 1);
+        assert_eq!(wrap_row(&points, 10), 2);
+    }
+    #[test]
+    fn counting_matches() {
+        let text = PieceTable::from_bytes(b"ab ab\nx\nab".to_vec());
+        let pattern = Pattern::new("ab", search::Options::default()).unwrap();
+        let starts = match_starts(&text, &pattern);
+        assert_eq!(starts, [(0, 0), (0, 3), (2, 0)]);
+        assert_eq!(match_count(&starts, (0, 3)), "2 of 3");
+        assert_eq!(match_count(&starts, (2, 0)), "3 of 3");
+
+        // a lot of them stops the count
+        let text = PieceTable::from_bytes(b"a".repeat(MEGA_MAX_MATCHES * 2));
+        let pattern = Pattern::new("a", search::Options::default()).unwrap();
+        let starts = match_starts(&text, &pattern);
+        assert_eq!(starts.len(), MEGA_MAX_MATCHES + 1);
+        assert_eq!(match_count(&starts, (0, 4)), "5 of 1000+");
+        assert_eq!(match_count(&starts, (0, 1500)), "1000+ of 1000+");
+    }
+    #[test]
+    fn pasting_into_a_prompt() {
+        assert_eq!(prompt_paste(b"foo bar"), "foo bar");
+        assert_eq!(prompt_paste(b"one\r\ntwo"), "one");
+        assert_eq!(prompt_paste(b"one\ntwo"), "one");
+        assert_eq!(prompt_paste(b"\nnothing"), "");
+        assert_eq!(prompt_paste(b"a\tb\x1bc\xff"), "abc\u{fffd}");
+    }
 }
@@ -0,0 +1,273 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::{MetadataExt, OpenOptionsExt, fchown},
+    path::{Path, PathBuf},
+    process,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Mixed, // the '\r's stay in the text, so the file goes back exactly how it came
+}
+
+impl LineEnding {
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+            LineEnding::Mixed => "Mixed",
+        }
+    }
+}
+
+// a file split into what goes into the piece table, and what it takes to write it back the same
+pub struct Contents {
+    pub text: Vec<u8>,
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub invalid_utf8: bool,
+}
+
+pub fn decode(bytes: &[u8]) -> Contents {
+    let crlf = bytes.windows(2).filter(|pair| pair == b"\r\n").count();
+    let lf = bytes.iter().filter(|&&b| b == b'\n').count() - crlf;
+    let line_ending = match (crlf, lf) {
+        (0, _) => LineEnding::Lf,
+        (_, 0) => LineEnding::Crlf,
+        _ => LineEnding::Mixed,
+    };
+    let final_newline = bytes.ends_with(b"\n");
+
+    let mut lines: Vec<&[u8]> = bytes.split(|&b| b == b'\n').collect();
+    if final_newline {
+        lines.pop();
+    }
+    if line_ending == LineEnding::Crlf {
+        // only lines that had a newline after them end in a '\r' that belongs to it
+        let terminated = if final_newline {
+            lines.len()
+        } else {
+            lines.len() - 1
+        };
+        for line in &mut lines[..terminated] {
+            *line = line.strip_suffix(b"\r").unwrap_or(line);
+        }
+    }
+
+    Contents {
+        text: lines.join(&b'\n'),
+        line_ending,
+        final_newline,
+        invalid_utf8: std::str::from_utf8(bytes).is_err(),
+    }
+}
+
+pub fn encode(text: &[u8], line_ending: LineEnding, final_newline: bool) -> Vec<u8> {
+    let newline: &[u8] = match line_ending {
+        LineEnding::Crlf => b"\r\n",
+        LineEnding::Lf | LineEnding::Mixed => b"\n",
+    };
+
+    let mut bytes = Vec::with_capacity(text.len() + text.len() / 32);
+    for (i, line) in text.split(|&b| b == b'\n').enumerate() {
+        if i > 0 {
+            bytes.extend_from_slice(newline);
+        }
+        bytes.extend_from_slice(line);
+    }
+    if final_newline {
+        bytes.extend_from_slice(newline);
+    }
+    bytes
+}
+
+// writes to a temp file next to the real one and renames it over the top, so a crash or a full
+// disk halfway through leaves the old file alone instead of a truncated one
+pub fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let target = resolve_symlinks(path)?;
+    let metadata = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None, // a new file
+        Err(error) => return Err(error),
+    };
+
+    if let Some(metadata) = &metadata
+        && metadata.nlink() > 1
+    {
+        // renaming would split it off from its other hard links
+        return write_in_place(&target, bytes);
+    }
+
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{}.{}.mega-save", name, process::id()));
+
+    let result = write_temp(&temp, bytes, metadata.as_ref());
+    match result {
+        Ok(true) => {}
+        Ok(false) => {
+            // couldnt give the new file the old owner, so renaming would change who owns it
+            let _ = fs::remove_file(&temp);
+            return write_in_place(&target, bytes);
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&temp);
+            return Err(error);
+        }
+    }
+
+    if let Err(error) = fs::rename(&temp, &target) {
+        let _ = fs::remove_file(&temp);
+        return Err(error);
+    }
+    // the rename only sticks after a crash once the directory itself is synced
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// returns false if the temp file couldnt be given the original owner. the owner and mode are
+// sorted out before anything is written, so the text is never readable by more people than the
+// original allowed, even for a moment
+fn write_temp(temp: &Path, bytes: &[u8], metadata: Option<&fs::Metadata>) -> io::Result<bool> {
+    // a new file gets the usual mode less the umask, a replacement starts out private
+    let mode = if metadata.is_some() { 0o600 } else { 0o666 };
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(temp)?;
+
+    if let Some(metadata) = metadata {
+        if (file.metadata()?.uid(), file.metadata()?.gid()) != (metadata.uid(), metadata.gid())
+            && fchown(&file, Some(metadata.uid()), Some(metadata.gid())).is_err()
+        {
+            return Ok(false);
+        }
+        // after the chown, which can clear the setuid and setgid bits
+        file.set_permissions(metadata.permissions())?;
+    }
+
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+// the fallback when we cant swap the file out, not atomic but keeps the inode and its owner
+fn write_in_place(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+// saving through a symlink should change the file it points at, not replace the link
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) if link.is_relative() => parent.join(link),
+                    _ => link,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other("too many levels of symbolic links"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what decode makes of bytes, and that encode puts them back exactly
+    fn round_trip(bytes: &[u8]) -> Contents {
+        let contents = decode(bytes);
+        let encoded = encode(&contents.text, contents.line_ending, contents.final_newline);
+        assert_eq!(encoded, bytes);
+        contents
+    }
+
+    #[test]
+    fn lf() {
+        let contents = round_trip(b"a\nb\n");
+        assert_eq!(contents.text, b"a\nb");
+        assert_eq!(contents.line_ending, LineEnding::Lf);
+        assert!(contents.final_newline);
+        assert!(!contents.invalid_utf8);
+    }
+
+    #[test]
+    fn crlf() {
+        let contents = round_trip(b"a\r\nb\r\n");
+        assert_eq!(contents.text, b"a\nb");
+        assert_eq!(contents.line_ending, LineEnding::Crlf);
+        assert!(contents.final_newline);
+        // a '\r' with no newline after it is just text
+        let contents = round_trip(b"a\r\nb\r");
+        assert_eq!(contents.text, b"a\nb\r");
+        assert!(!contents.final_newline);
+    }
+
+    #[test]
+    fn mixed_keeps_the_carriage_returns() {
+        let contents = round_trip(b"a\r\nb\nc");
+        assert_eq!(contents.text, b"a\r\nb\nc");
+        assert_eq!(contents.line_ending, LineEnding::Mixed);
+        assert!(!contents.final_newline);
+    }
+
+    #[test]
+    fn no_final_newline() {
+        let contents = round_trip(b"a\nb");
+        assert_eq!(contents.text, b"a\nb");
+        assert!(!contents.final_newline);
+        let contents = round_trip(b"");
+        assert_eq!(contents.text, b"");
+        assert_eq!(contents.line_ending, LineEnding::Lf);
+        assert!(!contents.final_newline);
+        let contents = round_trip(b"\n\n");
+        assert_eq!(contents.text, b"\n");
+    }
+
+    #[test]
+    fn invalid_utf8_is_kept_as_is() {
+        let contents = round_trip(b"a\xff\r\n\xc3\r\n");
+        assert_eq!(contents.text, b"a\xff\n\xc3");
+        assert!(contents.invalid_utf8);
+    }
+
+    #[test]
+    fn encode_converts() {
+        assert_eq!(encode(b"a\nb", LineEnding::Crlf, true), b"a\r\nb\r\n");
+        assert_eq!(encode(b"a\nb", LineEnding::Lf, false), b"a\nb");
+        assert_eq!(encode(b"", LineEnding::Crlf, true), b"\r\n");
+    }
+    #[test]
+    fn saving_keeps_the_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("mega-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("private");
+        fs::write(&path, b"old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        write(&path, b"new\n").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        assert_eq!(fs::read(&path).unwrap(), b"new\n");
+        // and the temp file is gone
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+use crate::{file::LineEnding, piece_table::PieceTable};
+
+// (cx, cy) to put the cursor back at
+pub type Cursor = (u32, u32);
+
+// how the text goes on disk, line endings and whether it ends with one
+pub type Format = (LineEnding, bool);
+
+#[derive(Debug)]
+pub enum Edit {
+    Insert { offset: usize, bytes: Vec<u8> },
+    Delete { offset: usize, bytes: Vec<u8> },
+}
+
+impl Edit {
+    fn apply(&self, text: &mut PieceTable) {
+        match self {
+            Edit::Insert { offset, bytes } => text.insert(*offset, bytes),
+            Edit::Delete { offset, bytes } => {
+                text.delete(*offset..*offset + bytes.len());
+            }
+        }
+    }
+    fn revert(&self, text: &mut PieceTable) {
+        match self {
+            Edit::Insert { offset, bytes } => {
+                text.delete(*offset..*offset + bytes.len());
+            }
+            Edit::Delete { offset, bytes } => text.insert(*offset, bytes),
+        }
+    }
+    // where the cursor ends up after the edit is done
+    fn end(&self) -> usize {
+        match self {
+            Edit::Insert { offset, bytes } => offset + bytes.len(),
+            Edit::Delete { offset, .. } => *offset,
+        }
+    }
+}
+
+// what produced an edit, so runs of the same thing can be undone together
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditKind {
+    Typing,
+    Backspace,
+    Delete,
+    Batch, // everything recorded until the next seal() is undone in one go
+    Other,
+}
+
+struct Group {
+    id: usize,
+    kind: EditKind,
+    edits: Vec<Edit>,
+    cursor_before: Cursor,
+    open: bool,
+    format: Option<(Format, Format)>, // before and after, if the group converted line endings
+}
+
+impl Group {
+    // folds the edit into the last one in the group if it carries on right where that one left
+    // off, stopping at the start of each new word so an undo takes back about a word at a time
+    fn merge(&mut self, edit: &Edit) -> bool {
+        let Some(last) = self.edits.last_mut() else {
+            return false;
+        };
+        match (self.kind, last, edit) {
+            (
+                EditKind::Typing,
+                Edit::Insert { offset, bytes },
+                Edit::Insert {
+                    offset: new_offset,
+                    bytes: new_bytes,
+                },
+            ) if *new_offset == *offset + bytes.len()
+                && !starts_word(bytes.last(), new_bytes.first()) =>
+            {
+                bytes.extend_from_slice(new_bytes);
+                true
+            }
+            (
+                EditKind::Backspace,
+                Edit::Delete { offset, bytes },
+                Edit::Delete {
+                    offset: new_offset,
+                    bytes: new_bytes,
+                },
+            ) if *new_offset + new_bytes.len() == *offset
+                && !starts_word(bytes.first(), new_bytes.last()) =>
+            {
+                let mut joined = new_bytes.clone();
+                joined.append(bytes);
+                *bytes = joined;
+                *offset = *new_offset;
+                true
+            }
+            (
+                EditKind::Delete,
+                Edit::Delete { offset, bytes },
+                Edit::Delete {
+                    offset: new_offset,
+                    bytes: new_bytes,
+                },
+            ) if *new_offset == *offset && !starts_word(bytes.last(), new_bytes.first()) => {
+                bytes.extend_from_slice(new_bytes);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct History {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    next_id: usize,
+    saved: usize, // id of the group on top of the undo stack when the file was saved, 0 if none
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            next_id: 1,
+            saved: 0,
+        }
+    }
+
+    // `edit` must already have been applied to the text. `cursor` is where the cursor was before it
+    pub fn record(&mut self, edit: Edit, kind: EditKind, cursor: Cursor) {
+        self.redo.clear();
+
+        if let Some(group) = self.undo.last_mut()
+            && group.open
+            && group.kind == kind
+        {
+            if kind == EditKind::Batch {
+                group.edits.push(edit);
+                return;
+            }
+            if kind != EditKind::Other && group.merge(&edit) {
+                return;
+            }
+        }
+
+        self.seal();
+        self.undo.push(Group {
+            id: self.next_id,
+            kind,
+            edits: vec![edit],
+            cursor_before: cursor,
+            open: true,
+            format: None,
+        });
+        self.next_id += 1;
+    }
+
+    // a change of line endings goes with the batch its edits are in, if there is one open, so
+    // undo puts the text and the format back together
+    pub fn record_format(&mut self, before: Format, after: Format, cursor: Cursor) {
+        self.redo.clear();
+
+        if let Some(group) = self.undo.last_mut()
+            && group.open
+            && group.kind == EditKind::Batch
+        {
+            let before = group.format.map_or(before, |(first, _)| first);
+            group.format = Some((before, after));
+            return;
+        }
+
+        self.seal();
+        self.undo.push(Group {
+            id: self.next_id,
+            kind: EditKind::Batch,
+            edits: Vec::new(),
+            cursor_before: cursor,
+            open: true,
+            format: Some((before, after)),
+        });
+        self.next_id += 1;
+    }
+
+    // stop the current group from growing, the next edit starts a new one
+    pub fn seal(&mut self) {
+        if let Some(group) = self.undo.last_mut() {
+            group.open = false;
+        }
+    }
+
+    pub fn undo(&mut self, text: &mut PieceTable, format: &mut Format) -> Option<Cursor> {
+        let mut group = self.undo.pop()?;
+        group.open = false;
+        for edit in group.edits.iter().rev() {
+            edit.revert(text);
+        }
+        if let Some((before, _)) = group.format {
+            *format = before;
+        }
+        let cursor = group.cursor_before;
+        self.redo.push(group);
+        Some(cursor)
+    }
+
+    pub fn redo(&mut self, text: &mut PieceTable, format: &mut Format) -> Option<Cursor> {
+        let group = self.redo.pop()?;
+        for edit in &group.edits {
+            edit.apply(text);
+        }
+        if let Some((_, after)) = group.format {
+            *format = after;
+        }
+        // a group that only changed the format leaves the cursor where it was
+        let cursor = match group.edits.last() {
+            Some(edit) => {
+                let (cy, cx) = text.position(edit.end());
+                (cx as u32, cy as u32)
+            }
+            None => group.cursor_before,
+        };
+        self.undo.push(group);
+        Some(cursor)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.seal(); // otherwise typing after a save would sneak into the saved group
+        self.saved = self.state();
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.state() == self.saved
+    }
+
+    fn state(&self) -> usize {
+        self.undo.last().map_or(0, |group| group.id)
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+// true when `next` begins a new word after `prev`
+fn starts_word(prev: Option<&u8>, next: Option<&u8>) -> bool {
+    match (prev, next) {
+        (Some(&prev), Some(&next)) => !is_word_byte(prev) && is_word_byte(next),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // applies the edit to the text and records it, like the editor does
+    fn edit(history: &mut History, text: &mut PieceTable, edit: Edit, kind: EditKind) {
+        edit.apply(text);
+        history.record(edit, kind, (0, 0));
+    }
+
+    fn insert(offset: usize, bytes: &[u8]) -> Edit {
+        Edit::Insert {
+            offset,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    fn delete(text: &PieceTable, offset: usize, len: usize) -> Edit {
+        Edit::Delete {
+            offset,
+            bytes: text.slice(offset..offset + len),
+        }
+    }
+
+    // undo and redo for tests that dont care about line endings
+    fn undo(history: &mut History, text: &mut PieceTable) -> Option<Cursor> {
+        history.undo(text, &mut (LineEnding::Lf, true))
+    }
+
+    fn redo(history: &mut History, text: &mut PieceTable) -> Option<Cursor> {
+        history.redo(text, &mut (LineEnding::Lf, true))
+    }
+
+    fn type_text(history: &mut History, text: &mut PieceTable, offset: usize, typed: &[u8]) {
+        for (i, &b) in typed.iter().enumerate() {
+            edit(history, text, insert(offset + i, &[b]), EditKind::Typing);
+        }
+    }
+
+    #[test]
+    fn typing_is_undone_a_word_at_a_time() {
+        let mut text = PieceTable::new();
+        let mut history = History::new();
+        type_text(&mut history, &mut text, 0, b"one two");
+        assert_eq!(undo(&mut history, &mut text), Some((0, 0)));
+        assert_eq!(text.bytes(), b"one ");
+        undo(&mut history, &mut text);
+        assert!(text.is_empty());
+        assert_eq!(undo(&mut history, &mut text), None);
+
+        assert_eq!(redo(&mut history, &mut text), Some((4, 0)));
+        assert_eq!(text.bytes(), b"one ");
+        assert_eq!(redo(&mut history, &mut text), Some((7, 0)));
+        assert_eq!(text.bytes(), b"one two");
+        assert_eq!(redo(&mut history, &mut text), None);
+    }
+
+    #[test]
+    fn typing_elsewhere_starts_a_new_group() {
+        let mut text = PieceTable::from_bytes(b"ab".to_vec());
+        let mut history = History::new();
+        type_text(&mut history, &mut text, 1, b"xy");
+        type_text(&mut history, &mut text, 0, b"z");
+        assert_eq!(text.bytes(), b"zaxyb");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"axyb");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"ab");
+    }
+
+    #[test]
+    fn backspace_and_delete_coalesce() {
+        let mut text = PieceTable::from_bytes(b"foo bar baz".to_vec());
+        let mut history = History::new();
+        // backspacing "bar" from its end, then delete eating into "baz"
+        for offset in (4..7).rev() {
+            let e = delete(&text, offset, 1);
+            edit(&mut history, &mut text, e, EditKind::Backspace);
+        }
+        for _ in 0..2 {
+            let e = delete(&text, 5, 1);
+            edit(&mut history, &mut text, e, EditKind::Delete);
+        }
+        assert_eq!(text.bytes(), b"foo  z");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"foo  baz");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"foo bar baz");
+        assert_eq!(undo(&mut history, &mut text), None);
+    }
+
+    #[test]
+    fn other_edits_never_merge() {
+        let mut text = PieceTable::from_bytes(b"ab".to_vec());
+        let mut history = History::new();
+        // even when they carry on from each other
+        edit(&mut history, &mut text, insert(2, b"c"), EditKind::Other);
+        edit(&mut history, &mut text, insert(3, b"c"), EditKind::Other);
+        assert_eq!(text.bytes(), b"abcc");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"abc");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"ab");
+    }
+
+    #[test]
+    fn batches_undo_in_one_go() {
+        let mut text = PieceTable::from_bytes(b"a a a".to_vec());
+        let mut history = History::new();
+        for offset in [0, 2, 4] {
+            let e = delete(&text, offset, 1);
+            edit(&mut history, &mut text, e, EditKind::Batch);
+            let e = insert(offset, b"b");
+            edit(&mut history, &mut text, e, EditKind::Batch);
+        }
+        history.seal();
+        edit(&mut history, &mut text, insert(5, b"c"), EditKind::Batch);
+        assert_eq!(text.bytes(), b"b b bc");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"b b b");
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"a a a");
+        assert_eq!(undo(&mut history, &mut text), None);
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut text = PieceTable::new();
+        let mut history = History::new();
+        type_text(&mut history, &mut text, 0, b"a");
+        undo(&mut history, &mut text);
+        type_text(&mut history, &mut text, 0, b"b");
+        assert_eq!(redo(&mut history, &mut text), None);
+        assert_eq!(text.bytes(), b"b");
+    }
+
+    #[test]
+    fn saved_state() {
+        let mut text = PieceTable::new();
+        let mut history = History::new();
+        assert!(history.is_saved());
+        type_text(&mut history, &mut text, 0, b"ab");
+        assert!(!history.is_saved());
+        history.mark_saved();
+        assert!(history.is_saved());
+
+        // typing on after a save goes in a new group, so undo gets back to the saved text
+        type_text(&mut history, &mut text, 2, b"c");
+        assert!(!history.is_saved());
+        undo(&mut history, &mut text);
+        assert_eq!(text.bytes(), b"ab");
+        assert!(history.is_saved());
+        undo(&mut history, &mut text);
+        assert!(!history.is_saved());
+        redo(&mut history, &mut text);
+        assert!(history.is_saved());
+
+        // once the saved state is dropped from redo it cant come back
+        undo(&mut history, &mut text);
+        type_text(&mut history, &mut text, 0, b"ab");
+        assert_eq!(text.bytes(), b"ab");
+        assert!(!history.is_saved());
+    }
+    #[test]
+    fn format_changes_undo_with_their_edits() {
+        const MIXED: Format = (LineEnding::Mixed, true);
+        const CRLF: Format = (LineEnding::Crlf, true);
+        const LF: Format = (LineEnding::Lf, true);
+        let mut text = PieceTable::from_bytes(b"a\r\nb\n".to_vec());
+        let mut history = History::new();
+        let mut format = MIXED;
+
+        // the carriage returns come out in the same batch as the switch to crlf
+        let e = delete(&text, 1, 1);
+        edit(&mut history, &mut text, e, EditKind::Batch);
+        history.record_format(MIXED, CRLF, (0, 0));
+        history.seal();
+        // and a plain conversion is a step of its own
+        history.record_format(CRLF, LF, (0, 0));
+        history.seal();
+        assert_eq!(text.bytes(), b"a\nb\n");
+
+        assert_eq!(history.undo(&mut text, &mut format), Some((0, 0)));
+        assert_eq!((text.bytes(), format), (b"a\nb\n".to_vec(), CRLF));
+        history.undo(&mut text, &mut format);
+        assert_eq!((text.bytes(), format), (b"a\r\nb\n".to_vec(), MIXED));
+        assert_eq!(history.undo(&mut text, &mut format), None);
+
+        history.redo(&mut text, &mut format);
+        assert_eq!((text.bytes(), format), (b"a\nb\n".to_vec(), CRLF));
+        assert_eq!(history.redo(&mut text, &mut format), Some((0, 0)));
+        assert_eq!(format, LF);
+    }
+}
@@ -0,0 +1,541 @@
+use std::{collections::VecDeque, io, time::Duration};
+
+use crate::{
+    keys::{Key, KeyCode, Modifiers},
+    terminal,
+};
+
+// how long read_key waits for anything at all before giving the editor a chance to look around
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// a terminal sends the whole of an escape sequence at once, so an ESC with nothing after it for
+// this long was the escape key itself
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
+// the longest CSI sequence we bother with, anything longer is thrown away
+const MAX_SEQUENCE: usize = 32;
+
+// a big paste can come through in pieces, so this is more patient than ESCAPE_TIMEOUT
+const PASTE_TIMEOUT: Duration = Duration::from_secs(1);
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+// something that happened at the terminal
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    Key(Key),
+    Mouse(Mouse),
+    Paste(Vec<u8>), // exactly what was pasted, it doesnt go through the key parsing
+}
+
+// row and col are 0 based screen positions
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mouse {
+    pub kind: MouseKind,
+    pub row: u16,
+    pub col: u16,
+    pub mods: Modifiers,
+}
+
+// only the left button, the others dont do anything yet
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseKind {
+    Press,
+    Drag,
+    Release,
+    WheelUp,
+    WheelDown,
+}
+
+// where the bytes come from, the terminal except in the tests
+pub trait Source {
+    // whatever has come in, or nothing if it didnt before the timeout
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+}
+
+pub struct Terminal;
+
+impl Source for Terminal {
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        if !terminal::poll_input(timeout)? {
+            return Ok(0);
+        }
+        terminal::read_input(buf)
+    }
+}
+
+// turns the bytes coming from the terminal into keys and mouse events
+pub struct Input<S = Terminal> {
+    source: S,
+    pending: VecDeque<u8>, // read from the terminal but not turned into a key yet
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Input::with_source(Terminal)
+    }
+}
+
+impl<S: Source> Input<S> {
+    fn with_source(source: S) -> Self {
+        Input {
+            source,
+            pending: VecDeque::new(),
+        }
+    }
+
+    // bytes that came in before the input was being read, they go ahead of anything new
+    pub fn type_ahead(&mut self, bytes: &[u8]) {
+        self.pending.extend(bytes);
+    }
+
+    // None if nothing happened in the meantime, or only something we dont understand
+    pub fn read_event(&mut self) -> io::Result<Option<Event>> {
+        match self.byte(POLL_INTERVAL)? {
+            Some(b'\x1b') => self.escape(),
+            Some(b) => Ok(self.plain(b)?.map(Event::Key)),
+            None => Ok(None),
+        }
+    }
+
+    fn byte(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            self.fill(timeout)?;
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    // false if nothing turned up before the timeout
+    fn fill(&mut self, timeout: Duration) -> io::Result<bool> {
+        let mut buf = [0u8; 4096];
+        let read = self.source.read(&mut buf, timeout)?;
+        self.pending.extend(&buf[..read]);
+        Ok(read > 0)
+    }
+
+    // everything up to the end of the bracketed paste, the start of it has already been read
+    fn paste(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        loop {
+            // the end marker could be split across two reads, so look back a little
+            let searched = bytes.len().saturating_sub(PASTE_END.len() - 1);
+            bytes.extend(self.pending.drain(..));
+            if let Some(end) = bytes[searched..]
+                .windows(PASTE_END.len())
+                .position(|window| window == PASTE_END)
+            {
+                let rest = bytes.split_off(searched + end);
+                // whatever came after the end belongs to the next key
+                self.pending.extend(&rest[PASTE_END.len()..]);
+                return Ok(bytes);
+            }
+            if !self.fill(PASTE_TIMEOUT)? {
+                return Ok(bytes); // the end never came, dont hang on to what we have
+            }
+        }
+    }
+
+    // a key that didnt come as an escape sequence, ascii, a control char or a utf-8 char
+    fn plain(&mut self, b: u8) -> io::Result<Option<Key>> {
+        let key = match b {
+            b'\r' => Key::new(KeyCode::Enter),
+            b'\t' => Key::new(KeyCode::Tab),
+            0x7f => Key::new(KeyCode::Backspace),
+            0x00 => Key::ctrl(' '),
+            0x01..=0x1a => Key::ctrl((b + 0x60) as char), // so 0x08 is ctrl-h, not backspace
+            0x1c..=0x1f => Key::ctrl((b + 0x40) as char),
+            0x20..=0x7e => Key::new(KeyCode::Char(b as char)),
+            _ => return self.utf8(b),
+        };
+        Ok(Some(key))
+    }
+
+    fn utf8(&mut self, first: u8) -> io::Result<Option<Key>> {
+        let len = match first {
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => 0, // stray continuation byte or a byte that never appears in utf-8
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for byte in bytes.iter_mut().take(len).skip(1) {
+            match self.byte(ESCAPE_TIMEOUT)? {
+                Some(b) if b & 0xc0 == 0x80 => *byte = b,
+                Some(b) => {
+                    // the start of the next key cut this one short, keep it for next time
+                    self.pending.push_front(b);
+                    return Ok(None);
+                }
+                None => return Ok(None),
+            }
+        }
+        // invalid or truncated, drop it instead of inserting garbage
+        Ok(std::str::from_utf8(&bytes[..len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .map(|c| Key::new(KeyCode::Char(c))))
+    }
+
+    fn escape(&mut self) -> io::Result<Option<Event>> {
+        let key = match self.byte(ESCAPE_TIMEOUT)? {
+            None => Some(Key::new(KeyCode::Escape)),
+            Some(b'[') => return self.csi(),
+            Some(b'O') => self.ss3()?,
+            Some(b'\x1b') => {
+                // escape pressed twice, the second one might be the start of a sequence
+                self.pending.push_front(b'\x1b');
+                Some(Key::new(KeyCode::Escape))
+            }
+            // alt+key comes as ESC and then the key
+            Some(b) => self.plain(b)?.map(|key| key.with(Modifiers::ALT)),
+        };
+        Ok(key.map(Event::Key))
+    }
+
+    // ESC [ then parameters and intermediates, then one final byte that says what it all means
+    fn csi(&mut self) -> io::Result<Option<Event>> {
+        let mut params = Vec::new();
+        loop {
+            match self.byte(ESCAPE_TIMEOUT)? {
+                None if params.is_empty() => {
+                    let key = Key::new(KeyCode::Char('[')).with(Modifiers::ALT);
+                    return Ok(Some(Event::Key(key)));
+                }
+                None => return Ok(None), // cut short, theres nothing sensible to make of it
+                Some(b @ 0x20..=0x3f) if params.len() < MAX_SEQUENCE => params.push(b),
+                Some(0x20..=0x3f) => {}
+                Some(b'~') if params == b"200" => return Ok(Some(Event::Paste(self.paste()?))),
+                Some(b @ 0x40..=0x7e) => match params.strip_prefix(b"<") {
+                    Some(params) => return Ok(mouse(params, b).map(Event::Mouse)),
+                    None => return Ok(csi_key(&params, b).map(Event::Key)),
+                },
+                Some(b) => {
+                    // not part of a sequence after all
+                    self.pending.push_front(b);
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    // ESC O then one byte, how some terminals send arrows, home/end and F1-F4
+    fn ss3(&mut self) -> io::Result<Option<Key>> {
+        let code = match self.byte(ESCAPE_TIMEOUT)? {
+            None => return Ok(Some(Key::new(KeyCode::Char('O')).with(Modifiers::ALT))),
+            Some(b) => match b {
+                b'A' => KeyCode::Up,
+                b'B' => KeyCode::Down,
+                b'C' => KeyCode::Right,
+                b'D' => KeyCode::Left,
+                b'H' => KeyCode::Home,
+                b'F' => KeyCode::End,
+                b'P'..=b'S' => KeyCode::F(b - b'P' + 1),
+                _ => return Ok(None),
+            },
+        };
+        Ok(Some(Key::new(code)))
+    }
+}
+
+fn csi_key(params: &[u8], last: u8) -> Option<Key> {
+    if params
+        .first()
+        .is_some_and(|b| !b.is_ascii_digit() && *b != b';')
+    {
+        return None; // some other private sequence, like replies to a query
+    }
+    let numbers = numbers(params);
+    let number = |i: usize, default| numbers.get(i).copied().flatten().unwrap_or(default);
+    let mods = Modifiers::from_param(number(1, 1));
+
+    let code = match last {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P'..=b'S' => KeyCode::F(last - b'P' + 1),
+        b'Z' => return Some(Key::new(KeyCode::Tab).with(Modifiers::SHIFT)),
+        // the kitty protocol, CSI unicode-key-code ; modifiers u
+        b'u' => match number(0, 0) {
+            9 => KeyCode::Tab,
+            13 => KeyCode::Enter,
+            27 => KeyCode::Escape,
+            127 => KeyCode::Backspace,
+            0xe000..=0xf8ff => return None, // keypad and lone modifier keys, nothing we use
+            n => KeyCode::Char(char::from_u32(n)?),
+        },
+        b'~' => match number(0, 0) {
+            1 | 7 => KeyCode::Home,
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            4 | 8 => KeyCode::End,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            n @ 11..=15 => KeyCode::F((n - 10) as u8),
+            n @ 17..=21 => KeyCode::F((n - 11) as u8),
+            n @ 23..=24 => KeyCode::F((n - 12) as u8),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Key::new(code).with(mods))
+}
+
+// SGR mouse reports, CSI < button ; col ; row, then M for a press or motion and m for a release
+fn mouse(params: &[u8], last: u8) -> Option<Mouse> {
+    let numbers = numbers(params);
+    let [Some(button), Some(col), Some(row)] = numbers[..] else {
+        return None;
+    };
+
+    let kind = match (button & 0b1100_0011, button & 32 != 0, last) {
+        (64, _, _) => MouseKind::WheelUp,
+        (65, _, _) => MouseKind::WheelDown,
+        (0, false, b'M') => MouseKind::Press,
+        (0, true, b'M') => MouseKind::Drag,
+        (0, _, b'm') => MouseKind::Release,
+        _ => return None,
+    };
+    let mut mods = Modifiers::NONE;
+    for (bit, modifier) in [
+        (4, Modifiers::SHIFT),
+        (8, Modifiers::ALT),
+        (16, Modifiers::CTRL),
+    ] {
+        if button & bit != 0 {
+            mods = mods | modifier;
+        }
+    }
+
+    Some(Mouse {
+        kind,
+        row: row.saturating_sub(1).min(u16::MAX as u32) as u16,
+        col: col.saturating_sub(1).min(u16::MAX as u32) as u16,
+        mods,
+    })
+}
+
+// numbers separated by ';', each may have ':' separated extras after it that we dont need
+fn numbers(params: &[u8]) -> Vec<Option<u32>> {
+    params
+        .split(|&b| b == b';')
+        .map(|param| {
+            let main = param.split(|&b| b == b':').next().unwrap_or_default();
+            std::str::from_utf8(main).ok()?.parse().ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // hands out one chunk per read, an empty chunk is a read that timed out
+    struct Script(VecDeque<&'static [u8]>);
+
+    impl Source for Script {
+        fn read(&mut self, buf: &mut [u8], _: Duration) -> io::Result<usize> {
+            let chunk = self.0.pop_front().unwrap_or_default();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    // everything the chunks turn into, None for the reads that didnt make anything
+    fn read(chunks: &[&'static [u8]]) -> Vec<Option<Event>> {
+        let mut input = Input::with_source(Script(chunks.iter().copied().collect()));
+        let mut events = Vec::new();
+        while !input.source.0.is_empty() || !input.pending.is_empty() {
+            events.push(input.read_event().unwrap());
+        }
+        events
+    }
+
+    fn key(code: KeyCode, mods: Modifiers) -> Option<Event> {
+        Some(Event::Key(Key::new(code).with(mods)))
+    }
+
+    fn char(c: char) -> Option<Event> {
+        key(KeyCode::Char(c), Modifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> Option<Event> {
+        Some(Event::Key(Key::ctrl(c)))
+    }
+
+    #[test]
+    fn plain_keys() {
+        assert_eq!(
+            read(&[b"a\r\t\x7f\x08\x00\x1c"]),
+            [
+                char('a'),
+                key(KeyCode::Enter, Modifiers::NONE),
+                key(KeyCode::Tab, Modifiers::NONE),
+                key(KeyCode::Backspace, Modifiers::NONE),
+                ctrl('h'),
+                ctrl(' '),
+                ctrl('\\'),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf8_split_across_reads() {
+        assert_eq!(
+            read(&[b"\xc3", b"\xa9\xe4\xb8", b"\xad"]),
+            [char('é'), char('中')]
+        );
+        // a stray byte, one cut short by a timeout and one cut short by the next key
+        assert_eq!(
+            read(&[b"\xff", b"\xe4\xb8", b"", b"\xe4a"]),
+            [None, None, None, char('a')]
+        );
+    }
+
+    #[test]
+    fn sequences_with_modifiers() {
+        assert_eq!(
+            read(&[b"\x1b[1;5C\x1b[3~\x1b[15;2~\x1b[Z\x1bOP\x1b[1;7H"]),
+            [
+                key(KeyCode::Right, Modifiers::CTRL),
+                key(KeyCode::Delete, Modifiers::NONE),
+                key(KeyCode::F(5), Modifiers::SHIFT),
+                key(KeyCode::Tab, Modifiers::SHIFT),
+                key(KeyCode::F(1), Modifiers::NONE),
+                key(KeyCode::Home, Modifiers::CTRL | Modifiers::ALT),
+            ]
+        );
+    }
+
+    #[test]
+    fn sequences_split_across_reads() {
+        assert_eq!(
+            read(&[b"\x1b", b"[1;", b"5A", b"\x1bO", b"F"]),
+            [
+                key(KeyCode::Up, Modifiers::CTRL),
+                key(KeyCode::End, Modifiers::NONE),
+            ]
+        );
+    }
+
+    #[test]
+    fn lone_escape_times_out() {
+        assert_eq!(
+            read(&[b"\x1b", b"", b"[A"]),
+            [key(KeyCode::Escape, Modifiers::NONE), char('['), char('A')]
+        );
+        // escape twice, then a sequence straight after
+        assert_eq!(
+            read(&[b"\x1b\x1b[B"]),
+            [
+                key(KeyCode::Escape, Modifiers::NONE),
+                key(KeyCode::Down, Modifiers::NONE),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_keys() {
+        assert_eq!(
+            read(&[b"\x1bx", b"\x1b[", b"", b"\x1bO", b""]),
+            [
+                key(KeyCode::Char('x'), Modifiers::ALT),
+                key(KeyCode::Char('['), Modifiers::ALT),
+                key(KeyCode::Char('O'), Modifiers::ALT),
+            ]
+        );
+    }
+
+    #[test]
+    fn kitty_keys() {
+        assert_eq!(
+            read(&[b"\x1b[105;5u\x1b[9u\x1b[127;5u\x1b[97;3u\x1b[57441;2u"]),
+            [
+                ctrl('i'),
+                key(KeyCode::Tab, Modifiers::NONE),
+                key(KeyCode::Backspace, Modifiers::CTRL),
+                key(KeyCode::Char('a'), Modifiers::ALT),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_sequences_are_dropped() {
+        assert_eq!(
+            read(&[b"\x1b[1;5X\x1b[?1u\x1b[99~\x1bOz", b"\x1b[1;", b"", b"a"]),
+            [None, None, None, None, None, char('a')]
+        );
+        // a byte that cant be in a sequence ends it and is kept as the next key
+        assert_eq!(read(&[b"\x1b[1\x01"]), [None, ctrl('a')]);
+    }
+
+    fn mouse(kind: MouseKind, row: u16, col: u16, mods: Modifiers) -> Option<Event> {
+        Some(Event::Mouse(Mouse {
+            kind,
+            row,
+            col,
+            mods,
+        }))
+    }
+
+    #[test]
+    fn sgr_mouse() {
+        assert_eq!(
+            read(&[b"\x1b[<0;10;5M\x1b[<32;11;5M\x1b[<0;12;6m\x1b[<64;1;1M\x1b[<65;1;1M"]),
+            [
+                mouse(MouseKind::Press, 4, 9, Modifiers::NONE),
+                mouse(MouseKind::Drag, 4, 10, Modifiers::NONE),
+                mouse(MouseKind::Release, 5, 11, Modifiers::NONE),
+                mouse(MouseKind::WheelUp, 0, 0, Modifiers::NONE),
+                mouse(MouseKind::WheelDown, 0, 0, Modifiers::NONE),
+            ]
+        );
+        assert_eq!(
+            read(&[b"\x1b[<20;3;", b"2M"]),
+            [mouse(
+                MouseKind::Press,
+                1,
+                2,
+                Modifiers::CTRL | Modifiers::SHIFT
+            )]
+        );
+        // other buttons, missing numbers and other finals
+        assert_eq!(
+            read(&[b"\x1b[<2;1;1M\x1b[<0;1M\x1b[<0;1;1X\x1b[<0;0;0M"]),
+            [
+                None,
+                None,
+                None,
+                mouse(MouseKind::Press, 0, 0, Modifiers::NONE)
+            ]
+        );
+    }
+
+    #[test]
+    fn bracketed_paste() {
+        assert_eq!(
+            read(&[b"\x1b[200~one\r\ntwo\x1b\x1b[201~a"]),
+            [Some(Event::Paste(b"one\r\ntwo\x1b".to_vec())), char('a')]
+        );
+        // the end marker split across reads, and a paste that never ends
+        assert_eq!(
+            read(&[b"\x1b[200~ab\x1b[2", b"01~", b"\x1b[200~c", b"d", b""]),
+            [
+                Some(Event::Paste(b"ab".to_vec())),
+                Some(Event::Paste(b"cd".to_vec())),
+            ]
+        );
+    }
+    #[test]
+    fn typed_ahead_comes_first() {
+        let mut input = Input::with_source(Script([&b"C"[..]].into()));
+        // the start of a sequence can be finished by what comes after
+        input.type_ahead(b"a\x1b[");
+        assert_eq!(input.read_event().unwrap(), char('a'));
+        assert_eq!(
+            input.read_event().unwrap(),
+            key(KeyCode::Right, Modifiers::NONE)
+        );
+    }
+}
@@ -1,2 +1,175 @@
-pub const BACKSPACE: char = 127 as char;
-pub const ENTER: char = '\r';
+use std::{fmt, ops::BitOr};
+
+// a key press as the terminal reported it, with whatever modifiers it could tell us about
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Key {
+    pub code: KeyCode,
+    pub mods: Modifiers,
+}
+
+impl Key {
+    pub const fn new(code: KeyCode) -> Self {
+        Key {
+            code,
+            mods: Modifiers::NONE,
+        }
+    }
+    pub const fn ctrl(c: char) -> Self {
+        Key {
+            code: KeyCode::Char(c),
+            mods: Modifiers::CTRL,
+        }
+    }
+    pub const fn alt(code: KeyCode) -> Self {
+        Key {
+            code,
+            mods: Modifiers::ALT,
+        }
+    }
+    // how keys are written in the config file, like "ctrl-s", "alt-left" or "f5"
+    pub fn parse(name: &str) -> Option<Key> {
+        let mut mods = Modifiers::NONE;
+        let mut rest = name;
+        // the last part is the key itself, which can be a "-" too
+        while let Some((modifier, after)) = rest.split_once('-')
+            && !after.is_empty()
+        {
+            mods = mods
+                | match modifier.to_ascii_lowercase().as_str() {
+                    "ctrl" => Modifiers::CTRL,
+                    "alt" | "meta" => Modifiers::ALT,
+                    "shift" => Modifiers::SHIFT,
+                    _ => return None,
+                };
+            rest = after;
+        }
+        let code = match rest.to_ascii_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "esc" | "escape" => KeyCode::Escape,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "insert" => KeyCode::Insert,
+            "delete" => KeyCode::Delete,
+            "space" => KeyCode::Char(' '),
+            f if f.len() > 1 && f.starts_with('f') => KeyCode::F(f[1..].parse().ok()?),
+            _ => {
+                let mut chars = rest.chars();
+                let c = chars.next()?;
+                if chars.next().is_some() {
+                    return None;
+                }
+                // see KeyCode::Char
+                if mods.contains(Modifiers::CTRL) {
+                    KeyCode::Char(c.to_ascii_lowercase())
+                } else {
+                    KeyCode::Char(c)
+                }
+            }
+        };
+        Some(Key { code, mods })
+    }
+    pub fn with(self, mods: Modifiers) -> Self {
+        Key {
+            code: self.code,
+            mods: self.mods | mods,
+        }
+    }
+}
+
+// for the help message, like "Ctrl-S"
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in [
+            (Modifiers::CTRL, "Ctrl-"),
+            (Modifiers::ALT, "Alt-"),
+            (Modifiers::SHIFT, "Shift-"),
+        ] {
+            if self.mods.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) if self.mods.contains(Modifiers::CTRL) => {
+                write!(f, "{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyCode {
+    Char(char), // lowercase when it comes with ctrl, legacy terminals cant tell the case apart
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+}
+
+impl KeyCode {
+    // keys that only move the cursor, shift with any of these extends the selection
+    pub fn is_movement(self) -> bool {
+        matches!(
+            self,
+            KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Home
+                | KeyCode::End
+                | KeyCode::PageUp
+                | KeyCode::PageDown
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Self = Modifiers(0);
+    pub const SHIFT: Self = Modifiers(1);
+    pub const ALT: Self = Modifiers(2);
+    pub const CTRL: Self = Modifiers(4);
+
+    // xterm sends these as 1 plus a bit each for shift, alt and ctrl, so 5 is ctrl on its own
+    pub fn from_param(param: u32) -> Self {
+        Modifiers((param.saturating_sub(1) & 0x7) as u8)
+    }
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Modifiers(self.0 | other.0)
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+
+// how many kills are kept around before the oldest ones get dropped
+const KILL_RING_SIZE: usize = 32;
+
+// everything cut or copied, newest first
+pub struct KillRing {
+    kills: VecDeque<Vec<u8>>,
+    yank: usize, // which kill the last paste used, so cycling knows where it is
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        KillRing {
+            kills: VecDeque::new(),
+            yank: 0,
+        }
+    }
+
+    pub fn push(&mut self, bytes: Vec<u8>) {
+        self.kills.push_front(bytes);
+        self.kills.truncate(KILL_RING_SIZE);
+    }
+
+    // for kills straight after another kill, they all come back together
+    pub fn append(&mut self, bytes: &[u8]) {
+        match self.kills.front_mut() {
+            Some(kill) => kill.extend_from_slice(bytes),
+            None => self.push(bytes.to_vec()),
+        }
+    }
+
+    pub fn newest(&self) -> Option<&[u8]> {
+        self.kills.front().map(Vec::as_slice)
+    }
+
+    // what a paste puts in
+    pub fn yank(&mut self) -> Option<&[u8]> {
+        self.yank = 0;
+        self.newest()
+    }
+
+    // the kill before the one last pasted, going back round to the newest after the oldest
+    pub fn rotate(&mut self) -> Option<&[u8]> {
+        if self.kills.is_empty() {
+            return None;
+        }
+        self.yank = (self.yank + 1) % self.kills.len();
+        self.kills.get(self.yank).map(Vec::as_slice)
+    }
+
+    // (which, out of how many) for the last paste, 1 being the newest
+    pub fn position(&self) -> (usize, usize) {
+        (self.yank + 1, self.kills.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yank_and_rotate() {
+        let mut ring = KillRing::new();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.rotate(), None);
+        for kill in [b"one", b"two", b"six"] {
+            ring.push(kill.to_vec());
+        }
+        assert_eq!(ring.yank(), Some(&b"six"[..]));
+        assert_eq!(ring.position(), (1, 3));
+        assert_eq!(ring.rotate(), Some(&b"two"[..]));
+        assert_eq!(ring.rotate(), Some(&b"one"[..]));
+        assert_eq!(ring.position(), (3, 3));
+        // back round to the newest
+        assert_eq!(ring.rotate(), Some(&b"six"[..]));
+        ring.rotate();
+        assert_eq!(ring.yank(), Some(&b"six"[..]));
+        assert_eq!(ring.position(), (1, 3));
+    }
+
+    #[test]
+    fn append_joins_the_newest() {
+        let mut ring = KillRing::new();
+        ring.append(b"a");
+        ring.append(b"b\n");
+        assert_eq!(ring.newest(), Some(&b"ab\n"[..]));
+        assert_eq!(ring.position(), (1, 1));
+    }
+
+    #[test]
+    fn oldest_kills_drop_off() {
+        let mut ring = KillRing::new();
+        for i in 0..KILL_RING_SIZE + 5 {
+            ring.push(vec![i as u8]);
+        }
+        assert_eq!(ring.position(), (1, KILL_RING_SIZE));
+        ring.yank();
+        for _ in 1..KILL_RING_SIZE {
+            ring.rotate();
+        }
+        assert_eq!(ring.kills.back(), Some(&vec![5]));
+        assert_eq!(ring.rotate(), Some(&[KILL_RING_SIZE as u8 + 4][..]));
+    }
+}
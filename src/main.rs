@@ -1,21 +1,47 @@
-use std::env::args;
+use std::{
+    env::args,
+    panic::{self, AssertUnwindSafe},
+    process,
+};
 
 use crate::editor::Editor;
 
+mod buffer;
 mod command;
+mod config;
 mod editor;
+mod file;
+mod history;
+mod input;
 mod keys;
+mod kill_ring;
+mod piece_table;
+mod regex;
+mod search;
+mod syntax;
 mod terminal;
+mod unicode;
+mod window;
 
 fn main() {
+    terminal::restore_on_panic();
     terminal::make_raw().unwrap();
+    let typed = terminal::enable_kitty_keyboard().unwrap();
+    terminal::watch_resize().unwrap();
+    terminal::watch_fatal_signals().unwrap();
 
     let mut editor = Editor::new();
+    editor.type_ahead(&typed);
 
-    if let Some(path) = args().nth(1) {
-        editor.open(path.into()).unwrap();
+    for path in args().skip(1) {
+        editor.open(path.into());
+    }
+    // the panic hook has already put the terminal back and printed the message by the time this
+    // returns, so all thats left is to rescue the buffer
+    if panic::catch_unwind(AssertUnwindSafe(|| editor.start())).is_err() {
+        editor.write_recovery();
+        process::exit(101); // skips dropping the editor, which would clear the message away
     }
-    editor.start();
 
     terminal::disable_raw().unwrap();
 }
@@ -0,0 +1,387 @@
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Source {
+    Original,
+    Added,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Piece {
+    source: Source,
+    start: usize,
+    len: usize,
+    newlines: usize, // cached so looking up a line doesnt have to rescan the bytes
+}
+
+// the document is the concatenation of the pieces, each one pointing into either the file as it
+// was loaded (never modified) or the append-only buffer of everything typed since. edits only
+// ever split or trim pieces, so inserting a line in a huge file doesnt move the file around
+pub struct PieceTable {
+    original: Vec<u8>,
+    added: Vec<u8>,
+    // sorted byte positions of every '\n' in the two source buffers, so we can find the nth line
+    // inside a piece with a binary search instead of scanning it
+    original_newlines: Vec<usize>,
+    added_newlines: Vec<usize>,
+    pieces: Vec<Piece>,
+    len: usize,
+    newlines: usize,
+}
+
+impl PieceTable {
+    pub fn new() -> Self {
+        Self::from_bytes(Vec::new())
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let original_newlines = newline_positions(&bytes, 0);
+        let len = bytes.len();
+        let newlines = original_newlines.len();
+        let pieces = if len > 0 {
+            vec![Piece {
+                source: Source::Original,
+                start: 0,
+                len,
+                newlines,
+            }]
+        } else {
+            Vec::new()
+        };
+
+        PieceTable {
+            original: bytes,
+            added: Vec::new(),
+            original_newlines,
+            added_newlines: Vec::new(),
+            pieces,
+            len,
+            newlines,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // an empty document still has one (empty) line
+    pub fn line_count(&self) -> usize {
+        self.newlines + 1
+    }
+
+    // byte offset of the first byte of `line`, or the end of the document if there is no such line
+    pub fn line_start(&self, line: usize) -> usize {
+        if line == 0 {
+            return 0;
+        }
+        if line > self.newlines {
+            return self.len;
+        }
+
+        // line n starts right after the nth newline
+        let mut remaining = line;
+        let mut offset = 0;
+        for piece in &self.pieces {
+            if piece.newlines >= remaining {
+                let newlines = self.source_newlines(piece.source);
+                let first = newlines.partition_point(|&pos| pos < piece.start);
+                let pos = newlines[first + remaining - 1];
+                return offset + (pos - piece.start) + 1;
+            }
+            remaining -= piece.newlines;
+            offset += piece.len;
+        }
+        self.len
+    }
+
+    // length of `line` in bytes, not counting the '\n'
+    pub fn line_len(&self, line: usize) -> usize {
+        let range = self.line_range(line);
+        range.end - range.start
+    }
+
+    pub fn line_range(&self, line: usize) -> Range<usize> {
+        let start = self.line_start(line);
+        let end = if line < self.newlines {
+            self.line_start(line + 1) - 1
+        } else {
+            self.len
+        };
+        start..end
+    }
+
+    pub fn line_bytes(&self, line: usize) -> Vec<u8> {
+        self.slice(self.line_range(line))
+    }
+
+    pub fn offset(&self, line: usize, col: usize) -> usize {
+        (self.line_start(line) + col).min(self.len)
+    }
+
+    // (line, column) of a byte offset
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.len);
+        let mut line = 0;
+        let mut pos = 0;
+        for piece in &self.pieces {
+            if offset < pos + piece.len {
+                line += self.count_newlines(piece.source, piece.start, offset - pos);
+                break;
+            }
+            line += piece.newlines;
+            pos += piece.len;
+        }
+        (line, offset - self.line_start(line))
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Vec<u8> {
+        let mut out = Vec::with_capacity(range.len());
+        let mut pos = 0;
+        for piece in &self.pieces {
+            let piece_end = pos + piece.len;
+            if piece_end > range.start && pos < range.end {
+                let from = range.start.max(pos) - pos;
+                let to = range.end.min(piece_end) - pos;
+                let source = self.source(piece.source);
+                out.extend_from_slice(&source[piece.start + from..piece.start + to]);
+            }
+            if piece_end >= range.end {
+                break;
+            }
+            pos = piece_end;
+        }
+        out
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.slice(0..self.len)
+    }
+
+    pub fn insert(&mut self, offset: usize, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let offset = offset.min(self.len);
+
+        let start = self.added.len();
+        self.added_newlines.extend(newline_positions(bytes, start));
+        self.added.extend_from_slice(bytes);
+        let new = self.make_piece(Source::Added, start, bytes.len());
+
+        self.len += new.len;
+        self.newlines += new.newlines;
+
+        let (idx, inner) = self.find_piece(offset);
+        if inner == 0 {
+            // typing usually appends right where the last insert ended, so just grow that piece
+            // instead of piling up one piece per keystroke
+            if idx > 0 {
+                let prev = &mut self.pieces[idx - 1];
+                if prev.source == Source::Added && prev.start + prev.len == start {
+                    prev.len += new.len;
+                    prev.newlines += new.newlines;
+                    return;
+                }
+            }
+            self.pieces.insert(idx, new);
+        } else {
+            let piece = self.pieces[idx];
+            let left = self.make_piece(piece.source, piece.start, inner);
+            let right = self.make_piece(piece.source, piece.start + inner, piece.len - inner);
+            self.pieces.splice(idx..=idx, [left, new, right]);
+        }
+    }
+
+    // removes the bytes in `range` and hands them back
+    pub fn delete(&mut self, range: Range<usize>) -> Vec<u8> {
+        let range = range.start.min(self.len)..range.end.min(self.len);
+        if range.is_empty() {
+            return Vec::new();
+        }
+        let removed = self.slice(range.clone());
+
+        let mut pieces = Vec::with_capacity(self.pieces.len() + 1);
+        let mut pos = 0;
+        for piece in &self.pieces {
+            let piece_start = pos;
+            let piece_end = pos + piece.len;
+            pos = piece_end;
+
+            if piece_end <= range.start || piece_start >= range.end {
+                pieces.push(*piece);
+                continue;
+            }
+            // keep whatever part of the piece sticks out on either side of the range
+            if piece_start < range.start {
+                pieces.push(self.make_piece(piece.source, piece.start, range.start - piece_start));
+            }
+            if piece_end > range.end {
+                let skip = range.end - piece_start;
+                pieces.push(self.make_piece(
+                    piece.source,
+                    piece.start + skip,
+                    piece_end - range.end,
+                ));
+            }
+        }
+        self.pieces = pieces;
+
+        self.len -= removed.len();
+        self.newlines -= removed.iter().filter(|&&b| b == b'\n').count();
+        removed
+    }
+
+    // index of the piece containing `offset` and how far into it the offset is. an offset on
+    // the boundary between two pieces belongs to the second one
+    fn find_piece(&self, offset: usize) -> (usize, usize) {
+        let mut pos = 0;
+        for (i, piece) in self.pieces.iter().enumerate() {
+            if offset < pos + piece.len {
+                return (i, offset - pos);
+            }
+            pos += piece.len;
+        }
+        (self.pieces.len(), 0)
+    }
+
+    fn make_piece(&self, source: Source, start: usize, len: usize) -> Piece {
+        Piece {
+            source,
+            start,
+            len,
+            newlines: self.count_newlines(source, start, len),
+        }
+    }
+
+    fn count_newlines(&self, source: Source, start: usize, len: usize) -> usize {
+        let newlines = self.source_newlines(source);
+        newlines.partition_point(|&pos| pos < start + len)
+            - newlines.partition_point(|&pos| pos < start)
+    }
+
+    fn source(&self, source: Source) -> &[u8] {
+        match source {
+            Source::Original => &self.original,
+            Source::Added => &self.added,
+        }
+    }
+
+    fn source_newlines(&self, source: Source) -> &[usize] {
+        match source {
+            Source::Original => &self.original_newlines,
+            Source::Added => &self.added_newlines,
+        }
+    }
+}
+
+fn newline_positions(bytes: &[u8], base: usize) -> Vec<usize> {
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b'\n')
+        .map(|(i, _)| base + i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every line of the table, checked against splitting its bytes on '\n'
+    fn check_lines(table: &PieceTable) {
+        let bytes = table.bytes();
+        let lines: Vec<&[u8]> = bytes.split(|&b| b == b'\n').collect();
+        assert_eq!(table.len(), bytes.len());
+        assert_eq!(table.line_count(), lines.len());
+        let mut start = 0;
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(table.line_start(i), start);
+            assert_eq!(table.line_bytes(i), *line);
+            assert_eq!(table.position(start + line.len()), (i, line.len()));
+            start += line.len() + 1;
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let table = PieceTable::new();
+        assert!(table.is_empty());
+        assert_eq!(table.line_count(), 1);
+        assert_eq!(table.line_bytes(0), b"");
+        assert_eq!(table.position(5), (0, 0));
+    }
+
+    #[test]
+    fn lines_and_positions() {
+        let table = PieceTable::from_bytes(b"one\ntwo\n\nfour".to_vec());
+        check_lines(&table);
+        assert_eq!(table.line_count(), 4);
+        assert_eq!(table.line_range(1), 4..7);
+        assert_eq!(table.offset(3, 2), 11);
+        // past the end clamps instead of panicking
+        assert_eq!(table.offset(3, 99), table.len());
+        assert_eq!(table.line_start(9), table.len());
+        assert_eq!(table.position(5), (1, 1));
+    }
+
+    #[test]
+    fn insert_and_delete() {
+        let mut table = PieceTable::from_bytes(b"hello world".to_vec());
+        table.insert(5, b",\nnew");
+        assert_eq!(table.bytes(), b"hello,\nnew world");
+        check_lines(&table);
+        table.insert(0, b">");
+        table.insert(table.len(), b"\n");
+        assert_eq!(table.bytes(), b">hello,\nnew world\n");
+        check_lines(&table);
+
+        assert_eq!(table.delete(6..12), b",\nnew ");
+        assert_eq!(table.bytes(), b">helloworld\n");
+        check_lines(&table);
+        assert_eq!(table.delete(5..99), b"oworld\n");
+        assert_eq!(table.delete(3..3), b"");
+        assert_eq!(table.bytes(), b">hell");
+    }
+
+    #[test]
+    fn typing_grows_one_piece() {
+        let mut table = PieceTable::from_bytes(b"ab".to_vec());
+        for (i, &b) in b"xyz".iter().enumerate() {
+            table.insert(1 + i, &[b]);
+        }
+        assert_eq!(table.bytes(), b"axyzb");
+        assert_eq!(table.pieces.len(), 3);
+    }
+
+    #[test]
+    fn matches_a_plain_vec() {
+        // a small lcg so the test doesnt need a rand dependency
+        let mut seed = 12345u64;
+        let mut next = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        let mut table = PieceTable::from_bytes(b"first\nsecond\nthird".to_vec());
+        let mut expected = table.bytes();
+        for _ in 0..500 {
+            let at = next(expected.len() + 1);
+            if next(3) == 0 {
+                let end = (at + next(8)).min(expected.len());
+                let removed: Vec<u8> = expected.drain(at..end).collect();
+                assert_eq!(table.delete(at..end), removed);
+            } else {
+                let bytes: &[u8] = [&b"a"[..], b"\n", b"bc", b"d\ne"][next(4)];
+                table.insert(at, bytes);
+                expected.splice(at..at, bytes.iter().copied());
+            }
+            assert_eq!(table.bytes(), expected);
+            check_lines(&table);
+        }
+    }
+}
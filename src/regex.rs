@@ -0,0 +1,749 @@
+use std::{cell::RefCell, fmt, ops::Range};
+
+// a small regex engine for search. the pattern is compiled to a program for a backtracking
+// matcher that remembers which (instruction, position) pairs it already tried, so no pattern can
+// take longer than program length times line length. when that gets too big for a line the
+// match fails with an error instead
+//
+// supports . [abc] [^a-z] \d \w \s (and \D \W \S) ^ $ \b \B, groups with ( ) and (?: ), |,
+// * + ? {n} {n,} {n,m} and their lazy *? versions, and (?i) at the start for ignoring case
+pub struct Regex {
+    program: Vec<Inst>,
+    ignore_case: bool,
+    visited: RefCell<Visited>, // kept between calls, search runs this on every line
+}
+
+#[derive(Debug)]
+pub struct Error {
+    message: &'static str,
+    position: Option<usize>, // in chars, which is what the user typed. None for matching errors
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{} at position {}", self.message, position),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// the bits for the matcher, and which words of them got set so the next line only has to clear
+// those rather than the whole thing
+#[derive(Default)]
+struct Visited {
+    bits: Vec<u64>,
+    touched: Vec<usize>,
+}
+
+// the most a {n,m} can repeat, anything bigger would blow up the program
+const MAX_REPEAT: u32 = 1000;
+// and the most instructions in all, since repeats inside repeats multiply. the matcher needs a
+// bit for every instruction at every position in the line
+const MAX_PROGRAM: usize = 20_000;
+// the most of those bits a line can take, 8MB worth
+const MAX_VISITED: usize = 1 << 26;
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let mut ignore_case = ignore_case;
+        if parser.eat_str("(?i)") {
+            ignore_case = true;
+        }
+        let node = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            // the only thing alternation() stops at early is a ')' with no '(' before it
+            return Err(parser.error("unmatched )"));
+        }
+
+        if size(&node) >= MAX_PROGRAM {
+            return Err(parser.error("pattern too big"));
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex {
+            program,
+            ignore_case,
+            visited: RefCell::new(Visited::default()),
+        })
+    }
+
+    // the leftmost match that starts at or after `start`
+    pub fn find_at(&self, hay: &[u8], start: usize) -> Result<Option<Range<usize>>, Error> {
+        let bits = self.program.len() * (hay.len() + 1);
+        if bits > MAX_VISITED {
+            return Err(Error {
+                message: "pattern too complex for this line",
+                position: None,
+            });
+        }
+        let mut visited = self.visited.borrow_mut();
+        let Visited {
+            bits: words,
+            touched,
+        } = &mut *visited;
+        for word in touched.drain(..) {
+            words[word] = 0;
+        }
+        let len = bits.div_ceil(64);
+        if words.len() < len {
+            words.resize(len, 0);
+        }
+        let mut matcher = Matcher {
+            regex: self,
+            hay,
+            visited: &mut words[..len],
+            touched,
+        };
+        let mut pos = start;
+        while pos <= hay.len() {
+            if let Some(end) = matcher.run(pos) {
+                return Ok(Some(pos..end));
+            }
+            pos += next_char(hay, pos).map_or(1, |(_, len)| len);
+        }
+        Ok(None)
+    }
+}
+
+// text that matches itself and nothing else
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary(bool), // false for \B
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn of(ranges: &[(char, char)], negated: bool) -> Self {
+        Class {
+            ranges: ranges.to_vec(),
+            negated,
+        }
+    }
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let contains = |c: char| self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+        let found =
+            contains(c) || (ignore_case && (contains(lower(c)) || c.to_uppercase().any(contains)));
+        found != self.negated
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &'static str) -> Error {
+        Error {
+            message,
+            position: Some(self.pos),
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn eat_str(&mut self, s: &str) -> bool {
+        let len = s.chars().count();
+        if self.chars.len() >= self.pos + len
+            && self.chars[self.pos..self.pos + len]
+                .iter()
+                .copied()
+                .eq(s.chars())
+        {
+            self.pos += len;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.concat()?];
+        while self.eat('|') {
+            branches.push(self.concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternation(branches)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repeat(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn repeat(&mut self, mut node: Node) -> Result<Node, Error> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.counts()? {
+                    Some(counts) => counts,
+                    None => return Ok(node), // not a repeat after all, the '{' is literal
+                },
+                _ => return Ok(node),
+            };
+            if self.pos == start {
+                self.pos += 1;
+            }
+            if matches!(node, Node::Start | Node::End | Node::WordBoundary(_)) {
+                self.pos = start;
+                return Err(self.error("nothing to repeat"));
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    // {n}, {n,} or {n,m}. None and nothing eaten if it isnt one of those
+    fn counts(&mut self) -> Result<Option<(u32, Option<u32>)>, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.number();
+        let max = if self.eat(',') { self.number() } else { min };
+        let is_counts = min.is_some() && self.eat('}');
+        if !is_counts {
+            self.pos = start;
+            return Ok(None);
+        }
+        let min = min.unwrap_or_default();
+        let too_big = min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT);
+        if too_big || max.is_some_and(|max| max < min) {
+            self.pos = start;
+            return Err(self.error(if too_big {
+                "repeat count too big"
+            } else {
+                "repeat counts out of order"
+            }));
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok().map(|n: u32| n.min(MAX_REPEAT + 1))
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        let start = self.pos;
+        let c = self.peek().ok_or_else(|| self.error("unexpected end"))?;
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                self.eat_str("?:"); // no captures anyway, so both kinds of group are the same
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    self.pos = start;
+                    return Err(self.error("unclosed ("));
+                }
+                node
+            }
+            '[' => Node::Class(self.class(start)?),
+            '\\' => self.escape()?,
+            '*' | '+' | '?' => {
+                self.pos = start;
+                return Err(self.error("nothing to repeat"));
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn escape(&mut self) -> Result<Node, Error> {
+        let c = self.peek().ok_or_else(|| self.error("trailing \\"))?;
+        self.pos += 1;
+        Ok(match c {
+            'b' => Node::WordBoundary(true),
+            'B' => Node::WordBoundary(false),
+            c => match class_escape(c) {
+                Some(class) => Node::Class(class),
+                None => Node::Char(char_escape(c)),
+            },
+        })
+    }
+
+    fn class(&mut self, start: usize) -> Result<Class, Error> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                self.pos = start;
+                return Err(self.error("unclosed ["));
+            };
+            self.pos += 1;
+            // a ] straight after the [ is just a ]
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                let escaped = self.peek().ok_or_else(|| self.error("trailing \\"))?;
+                self.pos += 1;
+                if let Some(class) = class_escape(escaped) {
+                    if class.negated {
+                        return Err(self.error("negated class inside []"));
+                    }
+                    ranges.extend(class.ranges);
+                    continue;
+                }
+                char_escape(escaped)
+            } else {
+                c
+            };
+
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']');
+            if !is_range {
+                ranges.push((lo, lo));
+                continue;
+            }
+            self.pos += 1;
+            let mut hi = self.chars[self.pos];
+            self.pos += 1;
+            if hi == '\\' {
+                hi = char_escape(self.peek().ok_or_else(|| self.error("trailing \\"))?);
+                self.pos += 1;
+            }
+            if hi < lo {
+                return Err(self.error("range out of order"));
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Class { ranges, negated })
+    }
+}
+
+fn class_escape(c: char) -> Option<Class> {
+    match c {
+        'd' => Some(Class::of(DIGIT, false)),
+        'D' => Some(Class::of(DIGIT, true)),
+        'w' => Some(Class::of(WORD, false)),
+        'W' => Some(Class::of(WORD, true)),
+        's' => Some(Class::of(SPACE, false)),
+        'S' => Some(Class::of(SPACE, true)),
+        _ => None,
+    }
+}
+
+fn char_escape(c: char) -> char {
+    match c {
+        't' => '\t',
+        'n' => '\n',
+        'r' => '\r',
+        c => c, // \. \( \\ and so on are just the char
+    }
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary(bool),
+    Split(usize, usize), // try the first, and if that fails the second
+    Jump(usize),
+    Match,
+}
+
+// how many instructions compile() would make, worked out first so a huge pattern never gets built
+fn size(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Concat(nodes) => nodes.iter().map(size).fold(0, usize::saturating_add),
+        Node::Alternation(branches) => branches
+            .iter()
+            .map(size)
+            .fold(2 * (branches.len() - 1), usize::saturating_add),
+        Node::Repeat { node, min, max, .. } => {
+            let body = size(node);
+            let required = body.saturating_mul(*min as usize);
+            let optional = match max {
+                None => body + 2,
+                Some(max) => (body + 1).saturating_mul((max - min) as usize),
+            };
+            required.saturating_add(optional)
+        }
+        _ => 1,
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::WordBoundary(b) => program.push(Inst::WordBoundary(*b)),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program);
+            }
+        }
+        Node::Alternation(branches) => {
+            // split to each branch in turn, every branch jumps past the rest when done
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program);
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    let next = program.len();
+                    program[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, program);
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            let split = |program: &mut Vec<Inst>, at: usize, body: usize, out: usize| {
+                program[at] = if *greedy {
+                    Inst::Split(body, out)
+                } else {
+                    Inst::Split(out, body)
+                };
+            };
+            match max {
+                None => {
+                    let start = program.len();
+                    program.push(Inst::Split(0, 0));
+                    compile(node, program);
+                    program.push(Inst::Jump(start));
+                    let end = program.len();
+                    split(program, start, start + 1, end);
+                }
+                Some(max) => {
+                    // each optional copy can bail out to the very end
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for at in splits {
+                        split(program, at, at + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Matcher<'a> {
+    regex: &'a Regex,
+    hay: &'a [u8],
+    visited: &'a mut [u64], // a bit for each (instruction, position) already tried
+    touched: &'a mut Vec<usize>,
+}
+
+impl Matcher<'_> {
+    // where the match that starts at `start` ends, if there is one
+    fn run(&mut self, start: usize) -> Option<usize> {
+        let mut stack = vec![(0, start)];
+        while let Some((mut pc, mut pos)) = stack.pop() {
+            loop {
+                let bit = pc * (self.hay.len() + 1) + pos;
+                let word = &mut self.visited[bit / 64];
+                if *word & (1 << (bit % 64)) != 0 {
+                    break; // been here before and it didnt work out then either
+                }
+                if *word == 0 {
+                    self.touched.push(bit / 64);
+                }
+                *word |= 1 << (bit % 64);
+
+                match &self.regex.program[pc] {
+                    Inst::Match => return Some(pos),
+                    Inst::Char(expected) => match next_char(self.hay, pos) {
+                        Some((c, len)) if self.same(c, *expected) => {
+                            pc += 1;
+                            pos += len;
+                        }
+                        _ => break,
+                    },
+                    Inst::Any => match next_char(self.hay, pos) {
+                        Some((c, len)) if c != '\n' => {
+                            pc += 1;
+                            pos += len;
+                        }
+                        _ => break,
+                    },
+                    Inst::Class(class) => match next_char(self.hay, pos) {
+                        Some((c, len)) if class.matches(c, self.regex.ignore_case) => {
+                            pc += 1;
+                            pos += len;
+                        }
+                        _ => break,
+                    },
+                    Inst::Start if pos == 0 => pc += 1,
+                    Inst::End if pos == self.hay.len() => pc += 1,
+                    Inst::WordBoundary(expected) if self.at_word_boundary(pos) == *expected => {
+                        pc += 1
+                    }
+                    Inst::Start | Inst::End | Inst::WordBoundary(_) => break,
+                    Inst::Split(first, second) => {
+                        stack.push((*second, pos));
+                        pc = *first;
+                    }
+                    Inst::Jump(to) => pc = *to,
+                }
+            }
+        }
+        None
+    }
+
+    fn same(&self, c: char, expected: char) -> bool {
+        c == expected || (self.regex.ignore_case && lower(c) == lower(expected))
+    }
+
+    fn at_word_boundary(&self, pos: usize) -> bool {
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let before = prev_char(self.hay, pos);
+        let after = next_char(self.hay, pos).map(|(c, _)| c);
+        is_word(before) != is_word(after)
+    }
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// the char at pos and how many bytes it takes. bytes that arent valid utf-8 come back one at a
+// time as U+FFFD, so they can still be matched by . and skipped over
+fn next_char(hay: &[u8], pos: usize) -> Option<(char, usize)> {
+    let first = *hay.get(pos)?;
+    let len = match first {
+        0x00..=0x7f => return Some((first as char, 1)),
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return Some((char::REPLACEMENT_CHARACTER, 1)),
+    };
+    hay.get(pos..pos + len)
+        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+        .and_then(|s| s.chars().next())
+        .map_or(Some((char::REPLACEMENT_CHARACTER, 1)), |c| Some((c, len)))
+}
+
+fn prev_char(hay: &[u8], pos: usize) -> Option<char> {
+    let start = (pos.saturating_sub(4)..pos)
+        .find(|&i| next_char(hay, i).is_some_and(|(_, len)| i + len == pos))?;
+    next_char(hay, start).map(|(c, _)| c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, hay: &str) -> Option<String> {
+        let regex = Regex::new(pattern, false).unwrap();
+        regex
+            .find_at(hay.as_bytes(), 0)
+            .unwrap()
+            .map(|m| hay[m].to_string())
+    }
+
+    fn error(pattern: &str) -> String {
+        Regex::new(pattern, false).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn alternation() {
+        assert_eq!(find("cat|dog", "hotdog").as_deref(), Some("dog"));
+        assert_eq!(find("(foo|bar)+", "zbarfoo!").as_deref(), Some("barfoo"));
+        assert_eq!(find("a|ab", "ab").as_deref(), Some("a")); // first branch wins
+        assert_eq!(find("x(?:y|z)", "xz").as_deref(), Some("xz"));
+        assert_eq!(find("cat|dog", "bird"), None);
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(find("[a-c]+", "xxcabz").as_deref(), Some("cab"));
+        assert_eq!(find("[^a-c]+", "abcxyz").as_deref(), Some("xyz"));
+        assert_eq!(find("[]a]+", "x]a]").as_deref(), Some("]a]"));
+        assert_eq!(find("[\\w-]+", "ab-c d").as_deref(), Some("ab-c"));
+        assert_eq!(find("\\d+", "abc 123").as_deref(), Some("123"));
+        assert_eq!(find("\\s\\S", "a b").as_deref(), Some(" b"));
+        assert_eq!(find("caf.", "café!").as_deref(), Some("café"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(find("^ab", "xab"), None);
+        assert_eq!(find("^ab", "abx").as_deref(), Some("ab"));
+        assert_eq!(find("ab$", "abab").as_deref(), Some("ab"));
+        let regex = Regex::new("ab$", false).unwrap();
+        assert_eq!(regex.find_at(b"abab", 0).unwrap(), Some(2..4));
+        assert_eq!(find("\\bword\\b", "swords word").as_deref(), Some("word"));
+        let regex = Regex::new("\\bword\\b", false).unwrap();
+        assert_eq!(regex.find_at(b"swords word", 0).unwrap(), Some(7..11));
+        assert_eq!(find("\\Bor\\B", "or word").as_deref(), Some("or"));
+    }
+
+    #[test]
+    fn repetition() {
+        assert_eq!(find("\\d{2,3}", "a12345").as_deref(), Some("123"));
+        assert_eq!(find("x{2}", "axxx").as_deref(), Some("xx"));
+        assert_eq!(find("x{2,}", "axxxx").as_deref(), Some("xxxx"));
+        assert_eq!(find("x{3}", "axx"), None);
+        assert_eq!(find("a.*b", "aXbYb").as_deref(), Some("aXbYb"));
+        assert_eq!(find("a.*?b", "aXbYb").as_deref(), Some("aXb"));
+        assert_eq!(find("ab?c", "ac").as_deref(), Some("ac"));
+        // not a repeat, so the braces are just text
+        assert_eq!(find("a{,2}", "a{,2}").as_deref(), Some("a{,2}"));
+        // would take forever without remembering what was tried
+        assert_eq!(find("(a*)*b", &"a".repeat(100)), None);
+    }
+
+    #[test]
+    fn case_folding() {
+        let regex = Regex::new("HeLLo", true).unwrap();
+        assert_eq!(regex.find_at(b"say hello", 0).unwrap(), Some(4..9));
+        assert_eq!(find("HeLLo", "say hello"), None);
+        assert_eq!(find("(?i)é", "É").as_deref(), Some("É"));
+        assert_eq!(find("(?i)[a-c]+", "xABCx").as_deref(), Some("ABC"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("(ab"), "unclosed ( at position 0");
+        assert_eq!(error("ab)"), "unmatched ) at position 2");
+        assert_eq!(error("*a"), "nothing to repeat at position 0");
+        assert!(error("[ab").starts_with("unclosed ["));
+        assert!(error("a{3,1}").starts_with("repeat counts out of order"));
+        assert!(error("a{1001}").starts_with("repeat count too big"));
+        assert!(error("\\").starts_with("trailing \\"));
+    }
+
+    #[test]
+    fn size_limit() {
+        assert!(error("(a{1000}){1000}").starts_with("pattern too big"));
+        assert!(error("((a{100}){100}){100}").starts_with("pattern too big"));
+        assert!(Regex::new("a{1000}b{1000}", false).is_ok());
+    }
+
+    #[test]
+    fn reuses_the_visited_bits() {
+        // a long line then a short one, the bits from the first mustnt leak into the second
+        let regex = Regex::new("b+", false).unwrap();
+        assert_eq!(regex.find_at(&[b'a'; 500], 0).unwrap(), None);
+        assert_eq!(regex.find_at(b"abb", 0).unwrap(), Some(1..3));
+        assert_eq!(regex.find_at(b"abb", 0).unwrap(), Some(1..3));
+    }
+
+    #[test]
+    fn too_complex_for_a_long_line() {
+        let regex = Regex::new("(a|b|c|d|e){400}", false).unwrap();
+        let long = vec![b'a'; 1 << 20];
+        let error = regex.find_at(&long, 0).unwrap_err();
+        assert_eq!(error.to_string(), "pattern too complex for this line");
+        assert_eq!(regex.find_at(b"x", 0).unwrap(), None);
+        // a simple one is fine on the same line
+        let regex = Regex::new("b", false).unwrap();
+        assert_eq!(regex.find_at(&long, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn escape_matches_literally() {
+        let text = "a.b*(c)[d]{1}^$|\\";
+        let regex = Regex::new(&escape(text), false).unwrap();
+        assert_eq!(
+            regex.find_at(text.as_bytes(), 0).unwrap(),
+            Some(0..text.len())
+        );
+    }
+}
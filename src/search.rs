@@ -0,0 +1,210 @@
+use std::ops::Range;
+
+use crate::{
+    regex::{self, Regex},
+    unicode,
+};
+
+// how find matches, kept between searches and toggled from the search prompt
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Options {
+    pub regex: bool,
+    pub case: Case,
+    pub whole_word: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Case {
+    #[default]
+    Sensitive,
+    Smart, // ignored unless the search has an uppercase letter in it
+    Insensitive,
+}
+
+impl Case {
+    pub fn next(self) -> Self {
+        match self {
+            Case::Sensitive => Case::Smart,
+            Case::Smart => Case::Insensitive,
+            Case::Insensitive => Case::Sensitive,
+        }
+    }
+}
+
+impl Options {
+    // the modes that are on, for showing in the prompt
+    pub fn describe(self) -> String {
+        let mut modes = String::new();
+        if self.regex {
+            modes.push_str(" [regex]");
+        }
+        match self.case {
+            Case::Sensitive => {}
+            Case::Smart => modes.push_str(" [smart case]"),
+            Case::Insensitive => modes.push_str(" [ignore case]"),
+        }
+        if self.whole_word {
+            modes.push_str(" [word]");
+        }
+        modes
+    }
+}
+
+// what find is looking for. plain text gets escaped into a regex too, so the case options work
+// the same either way
+pub struct Pattern {
+    regex: Regex,
+    whole_word: bool,
+}
+
+impl Pattern {
+    pub fn new(text: &str, options: Options) -> Result<Pattern, regex::Error> {
+        let ignore_case = match options.case {
+            Case::Sensitive => false,
+            Case::Smart => !has_uppercase(text, options.regex),
+            Case::Insensitive => true,
+        };
+        let regex = if options.regex {
+            Regex::new(text, ignore_case)?
+        } else {
+            Regex::new(&regex::escape(text), ignore_case)?
+        };
+        Ok(Pattern {
+            regex,
+            whole_word: options.whole_word,
+        })
+    }
+
+    // the first match in line at or after from. a regex can be too much for a long line, which
+    // is an error rather than no match
+    pub fn find(&self, line: &[u8], from: usize) -> Result<Option<Range<usize>>, regex::Error> {
+        let mut from = from;
+        while from <= line.len() {
+            let Some(m) = self.regex.find_at(line, from)? else {
+                break;
+            };
+            if !self.whole_word || is_whole_word(line, &m) {
+                return Ok(Some(m));
+            }
+            from = past(line, &m);
+        }
+        Ok(None)
+    }
+    // the last match in line that starts before end
+    pub fn rfind(&self, line: &[u8], end: usize) -> Result<Option<Range<usize>>, regex::Error> {
+        let matches = self.find_all(line)?;
+        Ok(matches.into_iter().take_while(|m| m.start < end).last())
+    }
+    // every match in line, stepping over whole matches so a regex doesnt find the tail of one
+    // it already found
+    pub fn find_all(&self, line: &[u8]) -> Result<Vec<Range<usize>>, regex::Error> {
+        let mut matches = Vec::new();
+        let mut from = 0;
+        while let Some(m) = self.find(line, from)? {
+            from = past(line, &m);
+            matches.push(m);
+        }
+        Ok(matches)
+    }
+}
+
+// where to look for the match after m, one char on for an empty one so it doesnt come up again
+pub fn past(line: &[u8], m: &Range<usize>) -> usize {
+    if !m.is_empty() {
+        m.end
+    } else if m.end < line.len() {
+        unicode::next_boundary(line, m.end)
+    } else {
+        line.len() + 1
+    }
+}
+
+// escapes like \W and \S dont count as uppercase in a regex
+fn has_uppercase(text: &str, regex: bool) -> bool {
+    let mut escaped = false;
+    for c in text.chars() {
+        if c.is_uppercase() && !escaped {
+            return true;
+        }
+        escaped = regex && c == '\\' && !escaped;
+    }
+    false
+}
+
+// nothing wordy right before or after, so searching for "in" doesnt stop inside "print"
+fn is_whole_word(line: &[u8], m: &Range<usize>) -> bool {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let before = String::from_utf8_lossy(&line[m.start.saturating_sub(4)..m.start])
+        .chars()
+        .next_back();
+    let after = String::from_utf8_lossy(&line[m.end..(m.end + 4).min(line.len())])
+        .chars()
+        .next();
+    !is_word(before) && !is_word(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(text: &str, regex: bool, case: Case, whole_word: bool) -> Pattern {
+        let options = Options {
+            regex,
+            case,
+            whole_word,
+        };
+        Pattern::new(text, options).unwrap()
+    }
+
+    #[test]
+    fn plain_text_is_escaped() {
+        let p = pattern("a.b", false, Case::Sensitive, false);
+        assert_eq!(p.find(b"axb a.b", 0).unwrap(), Some(4..7));
+    }
+
+    #[test]
+    fn case_modes() {
+        let line = b"Hello hello";
+        let sensitive = pattern("hello", false, Case::Sensitive, false);
+        assert_eq!(sensitive.find(line, 0).unwrap(), Some(6..11));
+        assert_eq!(sensitive.find_all(line).unwrap().len(), 1);
+        let insensitive = pattern("hello", false, Case::Insensitive, false);
+        assert_eq!(insensitive.find_all(line).unwrap(), [0..5, 6..11]);
+        // smart case ignores case until the search has an uppercase letter in it
+        let smart = pattern("hello", false, Case::Smart, false);
+        assert_eq!(smart.find_all(line).unwrap(), [0..5, 6..11]);
+        let smart = pattern("Hello", false, Case::Smart, false);
+        assert_eq!(smart.find_all(line).unwrap().len(), 1);
+        assert_eq!(smart.find(line, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn smart_case_skips_regex_escapes() {
+        assert!(!has_uppercase("\\W+\\S", true));
+        assert!(has_uppercase("\\W+S", true));
+        assert!(has_uppercase("\\W", false));
+        let smart = pattern("h\\S+", true, Case::Smart, false);
+        assert_eq!(smart.find(b"HELLO", 0).unwrap(), Some(0..5));
+    }
+
+    #[test]
+    fn whole_words() {
+        let p = pattern("in", false, Case::Sensitive, true);
+        assert_eq!(p.find_all(b"print in inside (in)").unwrap(), [6..8, 17..19]);
+        assert!(!is_whole_word(b"a_in b", &(2..4)));
+        // letters beyond ascii count as word chars too
+        assert!(!is_whole_word("éin".as_bytes(), &(2..4)));
+        assert!(is_whole_word("é in".as_bytes(), &(3..5)));
+    }
+
+    #[test]
+    fn find_all_steps_over_whole_matches() {
+        let p = pattern("\\d+", true, Case::Sensitive, false);
+        assert_eq!(p.find_all(b"123 45").unwrap(), [0..3, 4..6]);
+        assert_eq!(p.rfind(b"123 45", 4).unwrap(), Some(0..3));
+        assert_eq!(p.rfind(b"123 45", 5).unwrap(), Some(4..6));
+        // empty matches move on a char at a time instead of getting stuck
+        let p = pattern("x*", true, Case::Sensitive, false);
+        assert_eq!(p.find_all("é".as_bytes()).unwrap(), [0..0, 2..2]);
+    }
+}
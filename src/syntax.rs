@@ -0,0 +1,771 @@
+use std::path::Path;
+
+use crate::piece_table::PieceTable;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Highlight {
+    Normal,
+    Keyword,
+    Type,
+    String,
+    Number,
+    Comment,
+    MultilineComment,
+}
+
+// what is still open at the end of a line, so the next line knows how to start
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum State {
+    Normal,
+    BlockComment(usize), // nesting depth, only ever more than 1 for rust
+    String(u8),          // the quote that closes it
+    RawString(usize),    // number of '#'s in a rust raw string
+    TripleQuote(u8),
+    FencedCode, // markdown ``` block
+}
+
+pub struct Syntax {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    filenames: &'static [&'static str],
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    nested_comments: bool,
+    quotes: &'static [u8],
+    multiline_strings: bool, // otherwise a string only carries on if the line ends in a '\'
+    raw_strings: bool,       // rust r#"..."#
+    triple_quotes: bool,     // python/toml """...""" and '''...'''
+    lifetimes: bool,         // a ' that doesnt close right away is a rust lifetime, not a char
+    preprocessor: bool,      // c #include and friends
+    variables: bool,         // shell $VAR
+    table_headers: bool,     // toml [section]
+    markdown: bool,
+}
+
+const RUST: Syntax = Syntax {
+    name: "rust",
+    extensions: &["rs"],
+    filenames: &[],
+    keywords: &[
+        "as",
+        "async",
+        "await",
+        "break",
+        "const",
+        "continue",
+        "crate",
+        "dyn",
+        "else",
+        "enum",
+        "extern",
+        "false",
+        "fn",
+        "for",
+        "if",
+        "impl",
+        "in",
+        "let",
+        "loop",
+        "match",
+        "mod",
+        "move",
+        "mut",
+        "pub",
+        "ref",
+        "return",
+        "self",
+        "Self",
+        "static",
+        "struct",
+        "super",
+        "trait",
+        "true",
+        "type",
+        "unsafe",
+        "use",
+        "where",
+        "while",
+        "yield",
+        "macro_rules",
+    ],
+    types: &[
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+        "f32", "f64", "bool", "char", "str", "String", "Vec", "Option", "Result", "Box", "Some",
+        "None", "Ok", "Err",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    nested_comments: true,
+    quotes: b"\"'",
+    multiline_strings: true,
+    raw_strings: true,
+    triple_quotes: false,
+    lifetimes: true,
+    preprocessor: false,
+    variables: false,
+    table_headers: false,
+    markdown: false,
+};
+
+const C: Syntax = Syntax {
+    name: "c",
+    extensions: &["c", "h"],
+    filenames: &[],
+    keywords: &[
+        "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern",
+        "for", "goto", "if", "inline", "register", "restrict", "return", "sizeof", "static",
+        "struct", "switch", "typedef", "union", "volatile", "while", "NULL", "true", "false",
+    ],
+    types: &[
+        "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void", "bool",
+        "size_t", "ssize_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t",
+        "uint32_t", "uint64_t", "FILE",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    nested_comments: false,
+    quotes: b"\"'",
+    multiline_strings: false,
+    raw_strings: false,
+    triple_quotes: false,
+    lifetimes: false,
+    preprocessor: true,
+    variables: false,
+    table_headers: false,
+    markdown: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    name: "python",
+    extensions: &["py", "pyi", "pyw"],
+    filenames: &[],
+    keywords: &[
+        "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+        "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+        "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return",
+        "try", "while", "with", "yield", "match", "case", "self",
+    ],
+    types: &[
+        "int",
+        "float",
+        "complex",
+        "str",
+        "bytes",
+        "bytearray",
+        "bool",
+        "list",
+        "dict",
+        "set",
+        "frozenset",
+        "tuple",
+        "object",
+        "type",
+        "range",
+        "Exception",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    nested_comments: false,
+    quotes: b"\"'",
+    multiline_strings: false,
+    raw_strings: false,
+    triple_quotes: true,
+    lifetimes: false,
+    preprocessor: false,
+    variables: false,
+    table_headers: false,
+    markdown: false,
+};
+
+const TOML: Syntax = Syntax {
+    name: "toml",
+    extensions: &["toml"],
+    filenames: &["Cargo.lock"],
+    keywords: &["true", "false", "inf", "nan"],
+    types: &[],
+    line_comment: Some("#"),
+    block_comment: None,
+    nested_comments: false,
+    quotes: b"\"'",
+    multiline_strings: false,
+    raw_strings: false,
+    triple_quotes: true,
+    lifetimes: false,
+    preprocessor: false,
+    variables: false,
+    table_headers: true,
+    markdown: false,
+};
+
+const MARKDOWN: Syntax = Syntax {
+    name: "markdown",
+    extensions: &["md", "markdown"],
+    filenames: &[],
+    keywords: &[],
+    types: &[],
+    line_comment: None,
+    block_comment: Some(("<!--", "-->")),
+    nested_comments: false,
+    quotes: b"",
+    multiline_strings: false,
+    raw_strings: false,
+    triple_quotes: false,
+    lifetimes: false,
+    preprocessor: false,
+    variables: false,
+    table_headers: false,
+    markdown: true,
+};
+
+const SHELL: Syntax = Syntax {
+    name: "shell",
+    extensions: &["sh", "bash", "zsh", "ksh"],
+    filenames: &[".bashrc", ".bash_profile", ".profile", ".zshrc", "PKGBUILD"],
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "select", "return", "local", "export", "readonly", "declare", "unset",
+        "shift", "exit", "break", "continue", "source", "alias", "set", "trap", "eval", "exec",
+    ],
+    types: &[],
+    line_comment: Some("#"),
+    block_comment: None,
+    nested_comments: false,
+    quotes: b"\"'",
+    multiline_strings: true,
+    raw_strings: false,
+    triple_quotes: false,
+    lifetimes: false,
+    preprocessor: false,
+    variables: true,
+    table_headers: false,
+    markdown: false,
+};
+
+static SYNTAXES: [Syntax; 6] = [RUST, C, PYTHON, TOML, MARKDOWN, SHELL];
+
+impl Syntax {
+    pub fn for_filename(path: &Path) -> Option<&'static Syntax> {
+        let name = path.file_name()?.to_str()?;
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        SYNTAXES.iter().find(|syntax| {
+            syntax.filenames.contains(&name)
+                || extension.is_some_and(|ext| syntax.extensions.contains(&ext))
+        })
+    }
+
+    // for the filetype sections of the config
+    pub fn named(name: &str) -> Option<&'static Syntax> {
+        SYNTAXES.iter().find(|syntax| syntax.name == name)
+    }
+
+    // highlight for every byte of `line`, given the state the previous line ended in
+    pub fn highlight_line(&self, line: &[u8], state: &State) -> (Vec<Highlight>, State) {
+        if self.markdown {
+            return self.highlight_markdown(line, state);
+        }
+
+        let mut hl = vec![Highlight::Normal; line.len()];
+        let mut state = state.clone();
+        let mut prev_sep = true;
+        let mut i = 0;
+
+        while i < line.len() {
+            // first finish off whatever the previous line (or an earlier part of this one) opened
+            match state {
+                State::BlockComment(depth) => {
+                    let (start, end) = self.block_comment.unwrap_or(("", ""));
+                    if line[i..].starts_with(end.as_bytes()) {
+                        mark(&mut hl, i, end.len(), Highlight::MultilineComment);
+                        i += end.len();
+                        state = if depth > 1 {
+                            State::BlockComment(depth - 1)
+                        } else {
+                            State::Normal
+                        };
+                        prev_sep = true;
+                    } else if self.nested_comments && line[i..].starts_with(start.as_bytes()) {
+                        mark(&mut hl, i, start.len(), Highlight::MultilineComment);
+                        i += start.len();
+                        state = State::BlockComment(depth + 1);
+                    } else {
+                        hl[i] = Highlight::MultilineComment;
+                        i += 1;
+                    }
+                    continue;
+                }
+                State::String(quote) => {
+                    hl[i] = Highlight::String;
+                    if line[i] == b'\\' && i + 1 < line.len() {
+                        hl[i + 1] = Highlight::String;
+                        i += 2;
+                        continue;
+                    }
+                    if line[i] == quote {
+                        state = State::Normal;
+                        prev_sep = true;
+                    }
+                    i += 1;
+                    continue;
+                }
+                State::RawString(hashes) => {
+                    hl[i] = Highlight::String;
+                    if line[i] == b'"' && count_hashes(&line[i + 1..]) >= hashes {
+                        mark(&mut hl, i, hashes + 1, Highlight::String);
+                        i += hashes + 1;
+                        state = State::Normal;
+                        prev_sep = true;
+                    } else {
+                        i += 1;
+                    }
+                    continue;
+                }
+                State::TripleQuote(quote) => {
+                    if line[i..].starts_with(&[quote; 3]) {
+                        mark(&mut hl, i, 3, Highlight::String);
+                        i += 3;
+                        state = State::Normal;
+                        prev_sep = true;
+                    } else if line[i] == b'\\' && i + 1 < line.len() {
+                        mark(&mut hl, i, 2, Highlight::String);
+                        i += 2;
+                    } else {
+                        hl[i] = Highlight::String;
+                        i += 1;
+                    }
+                    continue;
+                }
+                State::Normal | State::FencedCode => {}
+            }
+
+            let c = line[i];
+
+            if let Some(comment) = self.line_comment
+                && line[i..].starts_with(comment.as_bytes())
+                // in shell a '#' in the middle of a word (like $#) isnt a comment
+                && (!self.variables || prev_sep)
+            {
+                mark(&mut hl, i, line.len() - i, Highlight::Comment);
+                break;
+            }
+
+            if let Some((start, _)) = self.block_comment
+                && line[i..].starts_with(start.as_bytes())
+            {
+                mark(&mut hl, i, start.len(), Highlight::MultilineComment);
+                i += start.len();
+                state = State::BlockComment(1);
+                continue;
+            }
+
+            if self.raw_strings && prev_sep && (c == b'r' || line[i..].starts_with(b"br")) {
+                let prefix = if c == b'r' { 1 } else { 2 };
+                let hashes = count_hashes(&line[i + prefix..]);
+                if line.get(i + prefix + hashes) == Some(&b'"') {
+                    let len = prefix + hashes + 1;
+                    mark(&mut hl, i, len, Highlight::String);
+                    i += len;
+                    state = State::RawString(hashes);
+                    continue;
+                }
+            }
+
+            if self.triple_quotes
+                && (line[i..].starts_with(b"\"\"\"") || line[i..].starts_with(b"'''"))
+            {
+                mark(&mut hl, i, 3, Highlight::String);
+                i += 3;
+                state = State::TripleQuote(c);
+                continue;
+            }
+
+            if self.quotes.contains(&c) {
+                if self.lifetimes && c == b'\'' && !is_char_literal(&line[i..]) {
+                    // 'a is a lifetime, leave it alone
+                    i += 1;
+                    prev_sep = false;
+                    continue;
+                }
+                hl[i] = Highlight::String;
+                i += 1;
+                state = State::String(c);
+                continue;
+            }
+
+            if self.preprocessor && c == b'#' && line[..i].iter().all(u8::is_ascii_whitespace) {
+                let len = 1 + word_len(&line[i + 1..]);
+                mark(&mut hl, i, len, Highlight::Keyword);
+                i += len;
+                prev_sep = true;
+                continue;
+            }
+
+            if self.variables && c == b'$' {
+                let len = match line.get(i + 1) {
+                    Some(b'{') => line[i..]
+                        .iter()
+                        .position(|&b| b == b'}')
+                        .map_or(1, |p| p + 1),
+                    Some(b) if is_word_byte(*b) => 1 + word_len(&line[i + 1..]),
+                    Some(_) => 2, // $?, $#, $@ and friends
+                    None => 1,
+                };
+                mark(&mut hl, i, len, Highlight::Type);
+                i += len;
+                prev_sep = true;
+                continue;
+            }
+
+            if self.table_headers && c == b'[' && line[..i].iter().all(u8::is_ascii_whitespace) {
+                let len = line[i..]
+                    .iter()
+                    .rposition(|&b| b == b']')
+                    .map_or(line.len() - i, |p| p + 1);
+                mark(&mut hl, i, len, Highlight::Type);
+                i += len;
+                prev_sep = true;
+                continue;
+            }
+
+            if prev_sep && c.is_ascii_digit() {
+                let mut len = 1;
+                while let Some(&b) = line.get(i + len) {
+                    // stop at a rust range like 0..10
+                    let number_byte = is_word_byte(b)
+                        || (b == b'.' && line.get(i + len + 1) != Some(&b'.'))
+                        || (matches!(b, b'+' | b'-') && matches!(line[i + len - 1], b'e' | b'E'));
+                    if !number_byte {
+                        break;
+                    }
+                    len += 1;
+                }
+                mark(&mut hl, i, len, Highlight::Number);
+                i += len;
+                prev_sep = false;
+                continue;
+            }
+
+            if prev_sep && is_word_byte(c) {
+                let len = word_len(&line[i..]);
+                let word = &line[i..i + len];
+                let is = |words: &[&str]| words.iter().any(|w| w.as_bytes() == word);
+                if is(self.keywords) {
+                    mark(&mut hl, i, len, Highlight::Keyword);
+                } else if is(self.types) {
+                    mark(&mut hl, i, len, Highlight::Type);
+                }
+                i += len;
+                prev_sep = false;
+                continue;
+            }
+
+            prev_sep = is_separator(c);
+            i += 1;
+        }
+
+        // only some languages let a plain string run onto the next line, c needs a '\' at the end
+        if let State::String(_) = state
+            && !self.multiline_strings
+            && line.last() != Some(&b'\\')
+        {
+            state = State::Normal;
+        }
+
+        (hl, state)
+    }
+
+    fn highlight_markdown(&self, line: &[u8], state: &State) -> (Vec<Highlight>, State) {
+        let mut hl = vec![Highlight::Normal; line.len()];
+        let trimmed_start = line.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        let trimmed = &line[trimmed_start..];
+
+        if trimmed.starts_with(b"```") || trimmed.starts_with(b"~~~") {
+            mark(&mut hl, 0, line.len(), Highlight::String);
+            let state = if *state == State::FencedCode {
+                State::Normal
+            } else {
+                State::FencedCode
+            };
+            return (hl, state);
+        }
+        if *state == State::FencedCode {
+            mark(&mut hl, 0, line.len(), Highlight::String);
+            return (hl, State::FencedCode);
+        }
+
+        let mut state = state.clone();
+        let mut i = 0;
+
+        if state == State::Normal {
+            if trimmed.starts_with(b"#") {
+                mark(&mut hl, 0, line.len(), Highlight::Keyword);
+                return (hl, state);
+            }
+            if trimmed.starts_with(b">") {
+                mark(&mut hl, 0, line.len(), Highlight::Comment);
+                return (hl, state);
+            }
+            // list bullets
+            let digits = trimmed.iter().take_while(|b| b.is_ascii_digit()).count();
+            let bullet = match trimmed.get(digits) {
+                Some(b'-' | b'*' | b'+') if digits == 0 => 1,
+                Some(b'.' | b')') if digits > 0 => digits + 1,
+                _ => 0,
+            };
+            if bullet > 0 && trimmed.get(bullet) == Some(&b' ') {
+                mark(&mut hl, trimmed_start, bullet, Highlight::Type);
+                i = trimmed_start + bullet;
+            }
+        }
+
+        while i < line.len() {
+            if let State::BlockComment(_) = state {
+                if line[i..].starts_with(b"-->") {
+                    mark(&mut hl, i, 3, Highlight::MultilineComment);
+                    i += 3;
+                    state = State::Normal;
+                } else {
+                    hl[i] = Highlight::MultilineComment;
+                    i += 1;
+                }
+                continue;
+            }
+            if line[i..].starts_with(b"<!--") {
+                mark(&mut hl, i, 4, Highlight::MultilineComment);
+                i += 4;
+                state = State::BlockComment(1);
+                continue;
+            }
+            if line[i] == b'`' {
+                // inline code, up to the closing backtick
+                let len = line[i + 1..]
+                    .iter()
+                    .position(|&b| b == b'`')
+                    .map_or(line.len() - i, |p| p + 2);
+                mark(&mut hl, i, len, Highlight::String);
+                i += len;
+                continue;
+            }
+            i += 1;
+        }
+
+        (hl, state)
+    }
+}
+
+// caches the state each line starts in, so drawing line n doesnt mean rescanning the whole file
+pub struct Highlighter {
+    syntax: Option<&'static Syntax>,
+    states: Vec<State>, // states[i] is the state at the start of line i
+}
+
+impl Highlighter {
+    pub fn new(syntax: Option<&'static Syntax>) -> Self {
+        Highlighter {
+            syntax,
+            states: vec![State::Normal],
+        }
+    }
+
+    pub fn syntax(&self) -> Option<&'static Syntax> {
+        self.syntax
+    }
+
+    // something changed on `line`, so everything after it might start differently now
+    pub fn invalidate(&mut self, line: usize) {
+        self.states.truncate(line + 1);
+    }
+
+    pub fn line(&mut self, text: &PieceTable, y: usize) -> Vec<Highlight> {
+        let Some(syntax) = self.syntax else {
+            return vec![Highlight::Normal; text.line_len(y)];
+        };
+
+        while self.states.len() <= y {
+            let prev = self.states.len() - 1;
+            let (_, state) = syntax.highlight_line(&text.line_bytes(prev), &self.states[prev]);
+            self.states.push(state);
+        }
+        syntax
+            .highlight_line(&text.line_bytes(y), &self.states[y])
+            .0
+    }
+}
+
+fn mark(hl: &mut [Highlight], start: usize, len: usize, highlight: Highlight) {
+    let end = (start + len).min(hl.len());
+    hl[start..end].fill(highlight);
+}
+
+fn is_separator(c: u8) -> bool {
+    c.is_ascii_whitespace() || b",.()+-/*=~%<>[];{}:&|!?^@#\"'`\\$".contains(&c)
+}
+
+fn is_word_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+fn word_len(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|&&b| is_word_byte(b)).count()
+}
+
+fn count_hashes(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|&&b| b == b'#').count()
+}
+
+// 'x', '\n' or '\u{..}' rather than a lifetime like 'a
+fn is_char_literal(bytes: &[u8]) -> bool {
+    match bytes.get(1) {
+        Some(b'\\') => true,
+        Some(_) => {
+            // the char might be several bytes long
+            let len = bytes[1..]
+                .iter()
+                .skip(1)
+                .take_while(|&&b| b & 0xc0 == 0x80)
+                .count();
+            bytes.get(2 + len) == Some(&b'\'')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax(filename: &str) -> &'static Syntax {
+        Syntax::for_filename(Path::new(filename)).unwrap()
+    }
+
+    // one letter per byte so the expected highlighting lines up under the line in the test
+    fn letters(hl: &[Highlight]) -> String {
+        hl.iter()
+            .map(|hl| match hl {
+                Highlight::Normal => '.',
+                Highlight::Keyword => 'k',
+                Highlight::Type => 't',
+                Highlight::String => 's',
+                Highlight::Number => 'n',
+                Highlight::Comment => 'c',
+                Highlight::MultilineComment => 'm',
+            })
+            .collect()
+    }
+
+    // each line in turn, carrying the state from one to the next like the editor does
+    fn highlight(filename: &str, lines: &[&str]) -> Vec<String> {
+        let syntax = syntax(filename);
+        let mut state = State::Normal;
+        lines
+            .iter()
+            .map(|line| {
+                let (hl, next) = syntax.highlight_line(line.as_bytes(), &state);
+                state = next;
+                letters(&hl)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn picks_a_syntax_by_filename() {
+        assert_eq!(syntax("src/main.rs").name, "rust");
+        assert_eq!(syntax("/home/me/.bashrc").name, "shell");
+        assert_eq!(syntax("Cargo.lock").name, "toml");
+        assert!(Syntax::for_filename(Path::new("notes.txt")).is_none());
+        assert!(Syntax::for_filename(Path::new("Makefile")).is_none());
+    }
+
+    #[test]
+    fn words_and_numbers() {
+        assert_eq!(
+            highlight("a.rs", &["let x: u8 = 10..2;", "match_x 1e-3"]),
+            ["kkk....tt...nn..n.", "........nnnn"]
+        );
+        assert_eq!(
+            highlight("a.sh", &["echo $HOME ${A}x $# # c", "if x#y; then"]),
+            [".....ttttt.tttt..tt.ccc", "kk......kkkk"]
+        );
+    }
+
+    #[test]
+    fn block_comments_carry_over() {
+        // rust comments nest, so one close isnt enough here
+        assert_eq!(
+            highlight("a.rs", &["a /* b /* c */", "still */ d", "e"]),
+            ["..mmmmmmmmmmmm", "mmmmmmmm..", "."]
+        );
+        assert_eq!(highlight("a.c", &["/* /* */ x"]), ["mmmmmmmm.."]);
+    }
+
+    #[test]
+    fn strings_carry_over() {
+        assert_eq!(
+            highlight("a.rs", &["let s = \"one", "two\\\" x\";"]),
+            ["kkk.....ssss", "ssssssss."]
+        );
+        // c needs a backslash at the end of the line to keep going
+        assert_eq!(
+            highlight("a.c", &["s = \"one", "two", "s = \"one\\", "two\" x"]),
+            ["....ssss", "...", "....sssss", "ssss.."]
+        );
+        assert_eq!(
+            highlight("a.rs", &["r#\"a \" b", "c \"# d"]),
+            ["ssssssss", "ssss.."]
+        );
+        assert_eq!(
+            highlight("a.py", &["x = '''a", "b''' # c"]),
+            ["....ssss", "ssss.ccc"]
+        );
+    }
+
+    #[test]
+    fn lifetimes_are_not_chars() {
+        assert_eq!(
+            highlight(
+                "a.rs",
+                &["fn f<'a>(c: char) -> bool { c == 'x' || c == '\\n' }"]
+            ),
+            ["kk..........tttt.....tttt........sss.........ssss.."]
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        let lines = [
+            "# title",
+            "- item `code` <!-- a",
+            "b --> c",
+            "```",
+            "# not a title",
+            "```",
+            "1. x",
+        ];
+        let expected = [
+            "kkkkkkk",
+            "t......ssssss.mmmmmm",
+            "mmmmm..",
+            "sss",
+            "sssssssssssss",
+            "sss",
+            "tt..",
+        ];
+        assert_eq!(highlight("a.md", &lines), expected);
+    }
+
+    #[test]
+    fn highlighter_rescans_after_a_change() {
+        let mut text = PieceTable::from_bytes(b"/*\nx\n*/\ny".to_vec());
+        let mut highlighter = Highlighter::new(Some(syntax("a.rs")));
+        assert_eq!(letters(&highlighter.line(&text, 3)), ".");
+        assert_eq!(letters(&highlighter.line(&text, 1)), "m");
+        text.delete(0..2);
+        highlighter.invalidate(0);
+        assert_eq!(letters(&highlighter.line(&text, 1)), ".");
+        assert_eq!(letters(&highlighter.line(&text, 2)), "..");
+        // without a syntax everything is normal
+        let mut plain = Highlighter::new(None);
+        assert_eq!(letters(&plain.line(&text, 2)), "..");
+    }
+}
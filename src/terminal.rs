@@ -1,23 +1,46 @@
 use std::{
-    io::{self, ErrorKind, stdin},
+    io::{self, ErrorKind, Write, stdin, stdout},
     os::fd::AsRawFd,
-    sync::OnceLock,
+    panic,
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, AtomicI32, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
+use crate::command;
+
+use rustix::{
+    event::{PollFd, PollFlags, Timespec, poll},
+    io::Errno,
+};
 use termios::{TCSAFLUSH, Termios, VMIN, VTIME, cfmakeraw, tcsetattr};
 
 static ORIG_TERMIOS: OnceLock<Termios> = OnceLock::new();
+static RESIZED: AtomicBool = AtomicBool::new(false);
+static FATAL_SIGNAL: AtomicI32 = AtomicI32::new(0);
+static KITTY_KEYBOARD: AtomicBool = AtomicBool::new(false);
+
+// how long to wait for the terminal to answer a query. if the answer turns up later anyway, the
+// input parser just throws it away
+const REPLY_TIMEOUT: Duration = Duration::from_millis(250);
 
 pub fn make_raw() -> io::Result<()> {
     let mut termios = Termios::from_fd(stdin().as_raw_fd())?;
-    let _ = ORIG_TERMIOS.set(termios.clone());
+    let _ = ORIG_TERMIOS.set(termios);
 
     cfmakeraw(&mut termios);
 
     termios.c_cc[VTIME] = 1;
     termios.c_cc[VMIN] = 0;
 
-    tcsetattr(stdin().as_raw_fd(), TCSAFLUSH, &mut termios)?;
+    tcsetattr(stdin().as_raw_fd(), TCSAFLUSH, &termios)?;
+
+    let mut stdout = stdout();
+    stdout.write_all(command::ENABLE_MOUSE)?;
+    stdout.write_all(command::ENABLE_BRACKETED_PASTE)?;
+    stdout.flush()?;
     Ok(())
 }
 
@@ -26,11 +49,179 @@ pub fn disable_raw() -> io::Result<()> {
         ErrorKind::NotFound,
         "need to make raw first",
     ))?;
-    tcsetattr(stdin().as_raw_fd(), TCSAFLUSH, &termios)?;
+    let mut stdout = stdout();
+    stdout.write_all(command::DISABLE_MOUSE)?;
+    stdout.write_all(command::DISABLE_BRACKETED_PASTE)?;
+    if KITTY_KEYBOARD.swap(false, Ordering::Relaxed) {
+        stdout.write_all(command::POP_KITTY_KEYBOARD)?;
+    }
+    stdout.flush()?;
+    tcsetattr(stdin().as_raw_fd(), TCSAFLUSH, termios)?;
     Ok(())
 }
 
+// turns on the kitty keyboard protocol if the terminal has it, so keys like ctrl-h and backspace
+// or ctrl-i and tab stop looking the same. every terminal answers the device attributes query,
+// so if the kitty one didnt get an answer by then the terminal doesnt know about it. returns
+// whatever was typed while waiting, which the input parser should still get
+pub fn enable_kitty_keyboard() -> io::Result<Vec<u8>> {
+    let mut stdout = stdout();
+    stdout.write_all(command::QUERY_KITTY_KEYBOARD)?;
+    stdout.write_all(command::QUERY_DEVICE_ATTRIBUTES)?;
+    stdout.flush()?;
+
+    let mut reply = Vec::new();
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    while !replies(&reply).0.contains(&b'c') {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || !poll_input(left)? {
+            break;
+        }
+        let mut buf = [0u8; 256];
+        let read = read_input(&mut buf)?;
+        reply.extend_from_slice(&buf[..read]);
+    }
+
+    let (finals, typed) = replies(&reply);
+    if finals.contains(&b'u') {
+        stdout.write_all(command::PUSH_KITTY_KEYBOARD)?;
+        stdout.flush()?;
+        KITTY_KEYBOARD.store(true, Ordering::Relaxed);
+    }
+    Ok(typed)
+}
+
+pub fn kitty_keyboard() -> bool {
+    KITTY_KEYBOARD.load(Ordering::Relaxed)
+}
+
+// the final bytes of the CSI ? ... replies in `bytes`, u for the kitty flags and c for the
+// device attributes, and the bytes that were around them. a reply cut off at the end is left
+// out of both, the rest of it hasnt come yet
+fn replies(bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let (mut finals, mut other) = (Vec::new(), Vec::new());
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(reply) = bytes[i..].strip_prefix(b"\x1b[?") {
+            let params = reply
+                .iter()
+                .take_while(|b| b.is_ascii_digit() || **b == b';')
+                .count();
+            match reply.get(params) {
+                Some(&last) => finals.push(last),
+                None => break,
+            }
+            i += 3 + params + 1;
+        } else {
+            other.push(bytes[i]);
+            i += 1;
+        }
+    }
+    (finals, other)
+}
+
+// true once there is something to read, false if the timeout ran out or a signal came in first
+pub fn poll_input(timeout: Duration) -> io::Result<bool> {
+    let stdin = stdin();
+    let mut fds = [PollFd::new(&stdin, PollFlags::IN)];
+    let timeout = Timespec::try_from(timeout).map_err(io::Error::other)?;
+    match poll(&mut fds, Some(&timeout)) {
+        Ok(ready) => Ok(ready > 0),
+        Err(Errno::INTR) => Ok(false),
+        Err(error) => Err(error.into()),
+    }
+}
+
+// straight from the fd, std's stdin buffers behind our back and then poll() wouldnt see it
+pub fn read_input(buf: &mut [u8]) -> io::Result<usize> {
+    Ok(rustix::io::read(stdin(), buf)?)
+}
+
 pub fn size() -> io::Result<(u16, u16)> {
     let winsize = rustix::termios::tcgetwinsize(stdin())?;
     Ok((winsize.ws_col, winsize.ws_row))
 }
+
+// puts the terminal back how we found it, for when the editor is going away without the usual
+// cleanup. errors are ignored since there is nothing left to report them to
+pub fn restore() {
+    let _ = disable_raw();
+    let mut stdout = stdout();
+    let _ = stdout.write_all(command::CLEAR_SCREEN);
+    let _ = stdout.write_all(command::MOVE_CURSOR_TOP_LEFT);
+    let _ = stdout.flush();
+}
+
+// the default hook prints the message, but into a raw mode screen that is about to be cleared
+pub fn restore_on_panic() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        default_hook(info);
+    }));
+}
+
+// the signal interrupts poll_input, so the editor notices the resize without a key being pressed
+pub fn watch_resize() -> io::Result<()> {
+    extern "C" fn on_resize(_: libc::c_int) {
+        RESIZED.store(true, Ordering::Relaxed);
+    }
+    handle(libc::SIGWINCH, on_resize)
+}
+
+// true once for every batch of SIGWINCHs since the last call
+pub fn resized() -> bool {
+    RESIZED.swap(false, Ordering::Relaxed)
+}
+
+// the handler only takes note, the editor checks fatal_signal() between reads and shuts down
+// from there, where it is safe to touch the buffer
+pub fn watch_fatal_signals() -> io::Result<()> {
+    extern "C" fn on_fatal(signal: libc::c_int) {
+        FATAL_SIGNAL.store(signal, Ordering::Relaxed);
+    }
+    for signal in [libc::SIGTERM, libc::SIGHUP, libc::SIGINT] {
+        handle(signal, on_fatal)?;
+    }
+    Ok(())
+}
+
+pub fn fatal_signal() -> Option<i32> {
+    match FATAL_SIGNAL.load(Ordering::Relaxed) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+// rustix cant install signal handlers, so this is the one place libc gets used directly
+fn handle(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) -> io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_and_typing_come_apart() {
+        assert_eq!(
+            replies(b"\x1b[?1u\x1b[?62;22c"),
+            (b"uc".to_vec(), Vec::new())
+        );
+        // keys typed before and in between the replies
+        assert_eq!(
+            replies(b"ab\x1b[?62;22cd\x1b[A"),
+            (b"c".to_vec(), b"abd\x1b[A".to_vec())
+        );
+        assert_eq!(replies(b"x\x1b[?62;2"), (Vec::new(), b"x".to_vec()));
+    }
+}
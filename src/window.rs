@@ -0,0 +1,310 @@
+use std::mem;
+
+use crate::buffer::View;
+
+// a part of the screen, in cells counting from 0 at the top left
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Rect {
+    pub top: u16,
+    pub left: u16,
+    pub rows: u16,
+    pub cols: u16,
+}
+
+// one view into a buffer, with its status line as the last row of rect. the focused window keeps
+// its cursor in the buffer itself while it has focus, view is where it goes the rest of the time
+pub struct Window {
+    pub buffer: usize,
+    pub view: View,
+    pub rect: Rect,
+}
+
+// how the screen is divided up between the windows, which are numbered by where they are in the
+// editors list of them
+pub enum Layout {
+    Window(usize),
+    Split {
+        vertical: bool, // side by side with a separator between, otherwise one above the other
+        ratio: f32,     // how much of the space the first one gets
+        extent: u16,    // the space there was to share last time, so resizing can go by cells
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    // gives every window its rect within area, and returns where the separators between side
+    // by side windows go
+    pub fn arrange(&mut self, area: Rect, windows: &mut [Window]) -> Vec<Rect> {
+        let mut separators = Vec::new();
+        self.arrange_into(area, windows, &mut separators);
+        separators
+    }
+
+    fn arrange_into(&mut self, area: Rect, windows: &mut [Window], separators: &mut Vec<Rect>) {
+        match self {
+            Layout::Window(window) => windows[*window].rect = area,
+            Layout::Split {
+                vertical: true,
+                ratio,
+                extent,
+                first,
+                second,
+            } => {
+                let space = area.cols.saturating_sub(1);
+                *extent = space;
+                let cols = share(space, *ratio, 1);
+                first.arrange_into(Rect { cols, ..area }, windows, separators);
+                separators.push(Rect {
+                    left: area.left + cols,
+                    cols: 1,
+                    ..area
+                });
+                let rest = Rect {
+                    left: area.left + cols + 1,
+                    cols: space - cols,
+                    ..area
+                };
+                second.arrange_into(rest, windows, separators);
+            }
+            Layout::Split {
+                vertical: false,
+                ratio,
+                extent,
+                first,
+                second,
+            } => {
+                *extent = area.rows;
+                // a window needs a row for its text and one for its status line
+                let rows = share(area.rows, *ratio, 2);
+                first.arrange_into(Rect { rows, ..area }, windows, separators);
+                let rest = Rect {
+                    top: area.top + rows,
+                    rows: area.rows - rows,
+                    ..area
+                };
+                second.arrange_into(rest, windows, separators);
+            }
+        }
+    }
+
+    // puts `new` next to `window`, sharing its space evenly
+    pub fn split(&mut self, window: usize, new: usize, vertical: bool) {
+        match self {
+            Layout::Window(w) if *w == window => {
+                *self = Layout::Split {
+                    vertical,
+                    ratio: 0.5,
+                    extent: 0,
+                    first: Box::new(Layout::Window(window)),
+                    second: Box::new(Layout::Window(new)),
+                };
+            }
+            Layout::Window(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.split(window, new, vertical);
+                second.split(window, new, vertical);
+            }
+        }
+    }
+
+    // takes `window` out, the one it shared with gets all the space, and the windows after it
+    // move down a number to match the list they index into
+    pub fn remove(&mut self, window: usize) {
+        self.take_out(window);
+        self.renumber(window);
+    }
+
+    fn take_out(&mut self, window: usize) {
+        let Layout::Split { first, second, .. } = self else {
+            return;
+        };
+        let left = if first.is(window) {
+            mem::replace(&mut **second, Layout::Window(0))
+        } else if second.is(window) {
+            mem::replace(&mut **first, Layout::Window(0))
+        } else {
+            first.take_out(window);
+            second.take_out(window);
+            return;
+        };
+        *self = left;
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::Window(w) if *w > removed => *w -= 1,
+            Layout::Window(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    // grows `window` by `delta` cells, or shrinks it if thats negative, by moving the edge it
+    // shares with its neighbour in the closest split. false if it has no neighbour
+    pub fn resize(&mut self, window: usize, delta: i16) -> bool {
+        let Layout::Split {
+            vertical,
+            ratio,
+            extent,
+            first,
+            second,
+        } = self
+        else {
+            return false;
+        };
+        if first.resize(window, delta) || second.resize(window, delta) {
+            return true;
+        }
+        let delta = if first.contains(window) {
+            delta
+        } else if second.contains(window) {
+            -delta
+        } else {
+            return false;
+        };
+        let min = if *vertical { 1 } else { 2 };
+        if *extent < min * 2 {
+            return true; // no room to move anything
+        }
+        let cells = share(*extent, *ratio, min) as i16 + delta;
+        let cells = cells.clamp(min as i16, (*extent - min) as i16);
+        *ratio = cells as f32 / *extent as f32;
+        true
+    }
+
+    // the windows from top left to bottom right, for cycling through them
+    pub fn order(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(window) => vec![*window],
+            Layout::Split { first, second, .. } => {
+                let mut order = first.order();
+                order.extend(second.order());
+                order
+            }
+        }
+    }
+
+    fn is(&self, window: usize) -> bool {
+        matches!(self, Layout::Window(w) if *w == window)
+    }
+
+    fn contains(&self, window: usize) -> bool {
+        self.order().contains(&window)
+    }
+}
+
+// how much of `space` the first of two windows gets, leaving each at least `min` if there is room
+fn share(space: u16, ratio: f32, min: u16) -> u16 {
+    let wanted = (space as f32 * ratio).round() as u16;
+    if space < min * 2 {
+        return space / 2;
+    }
+    wanted.clamp(min, space - min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect {
+        top: 0,
+        left: 0,
+        rows: 24,
+        cols: 80,
+    };
+
+    fn rect(top: u16, left: u16, rows: u16, cols: u16) -> Rect {
+        Rect {
+            top,
+            left,
+            rows,
+            cols,
+        }
+    }
+
+    // the rects the windows end up with, and the separators
+    fn arrange(layout: &mut Layout, count: usize, area: Rect) -> (Vec<Rect>, Vec<Rect>) {
+        let mut windows: Vec<Window> = (0..count)
+            .map(|buffer| Window {
+                buffer,
+                view: View::default(),
+                rect: Rect::default(),
+            })
+            .collect();
+        let separators = layout.arrange(area, &mut windows);
+        (windows.iter().map(|w| w.rect).collect(), separators)
+    }
+
+    #[test]
+    fn splits_share_the_space() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, true);
+        let (rects, separators) = arrange(&mut layout, 2, AREA);
+        assert_eq!(rects, [rect(0, 0, 24, 40), rect(0, 41, 24, 39)]);
+        assert_eq!(separators, [rect(0, 40, 24, 1)]);
+
+        layout.split(1, 2, false);
+        let (rects, _) = arrange(&mut layout, 3, AREA);
+        assert_eq!(
+            rects,
+            [
+                rect(0, 0, 24, 40),
+                rect(0, 41, 12, 39),
+                rect(12, 41, 12, 39)
+            ]
+        );
+        assert_eq!(layout.order(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn closing_gives_the_space_back() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, true);
+        layout.split(1, 2, false);
+        layout.remove(1);
+        // 2 is now 1
+        assert_eq!(layout.order(), [0, 1]);
+        let (rects, separators) = arrange(&mut layout, 2, AREA);
+        assert_eq!(rects, [rect(0, 0, 24, 40), rect(0, 41, 24, 39)]);
+        assert_eq!(separators.len(), 1);
+
+        layout.remove(0);
+        assert_eq!(layout.order(), [0]);
+        let (rects, separators) = arrange(&mut layout, 1, AREA);
+        assert_eq!(rects, [AREA]);
+        assert!(separators.is_empty());
+    }
+
+    #[test]
+    fn resizing_moves_the_shared_edge() {
+        let mut layout = Layout::Window(0);
+        assert!(!layout.resize(0, 1));
+
+        layout.split(0, 1, false);
+        arrange(&mut layout, 2, AREA);
+        assert!(layout.resize(0, 3));
+        let (rects, _) = arrange(&mut layout, 2, AREA);
+        assert_eq!(rects, [rect(0, 0, 15, 80), rect(15, 0, 9, 80)]);
+
+        // growing the second one moves the same edge the other way
+        assert!(layout.resize(1, 5));
+        let (rects, _) = arrange(&mut layout, 2, AREA);
+        assert_eq!(rects, [rect(0, 0, 10, 80), rect(10, 0, 14, 80)]);
+
+        // each keeps a row for its text and its status line
+        layout.resize(1, 100);
+        let (rects, _) = arrange(&mut layout, 2, AREA);
+        assert_eq!(rects, [rect(0, 0, 2, 80), rect(2, 0, 22, 80)]);
+    }
+
+    #[test]
+    fn tiny_screens_split_in_half() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, false);
+        let (rects, _) = arrange(&mut layout, 2, rect(0, 0, 3, 10));
+        assert_eq!(rects, [rect(0, 0, 1, 10), rect(1, 0, 2, 10)]);
+    }
+}